@@ -0,0 +1,254 @@
+use ffmpeg::util::frame::video::Video;
+use ffmpeg_next as ffmpeg;
+use float_ord::FloatOrd;
+
+use crate::{
+    frame::{FrameInfo, Matrix, SignalRange},
+    histogram::{Histogram, Percentiles},
+    pixel::{FormatError, FramePlanes, FrameReader},
+    pq::pq_to_nits,
+    transfer::Transfer,
+};
+
+/// How `Analyzer` measures frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnalyzerOptions {
+    /// How many of the brightest pixels of each frame `FrameInfo::robust_max` ignores.
+    pub ignore_brightest: u64,
+    /// Whether to measure the percentiles of each frame and over every pixel.
+    pub percentiles: bool,
+    /// Whether to keep a coarse histogram of each frame, which shot detection by histogram needs.
+    pub profiles: bool,
+    /// Whether to measure the distribution of each frame that HDR10+ metadata describes.
+    pub hdr10plus: bool,
+    /// How frames encode light.
+    pub transfer: Transfer,
+    /// The matrix Y'CbCr frames use, if not the default for the transfer.
+    pub matrix: Option<Matrix>,
+}
+
+impl AnalyzerOptions {
+    /// Measures a frame's planes. Without a signal range, Y'CbCr is taken to be limited range
+    /// and R'G'B' full range.
+    pub fn measure(
+        &self,
+        frame: usize,
+        time: Option<f64>,
+        planes: &FramePlanes,
+        range: Option<SignalRange>,
+    ) -> FrameInfo {
+        // Untagged HDR10 is overwhelmingly limited range, untagged RGB full range
+        let default_range = if planes.is_rgb() {
+            SignalRange::Full
+        } else {
+            SignalRange::Limited
+        };
+        let range = range.unwrap_or(default_range);
+
+        FrameInfo::measure(frame, time, planes, range, self)
+    }
+}
+
+/// Measures a stream of frames as they are fed in, and summarises them once they have all been.
+///
+/// Frames can be fed in any order. Analyzers that each measured part of a stream, on different
+/// threads say, can be merged.
+#[derive(Debug, Default)]
+pub struct Analyzer {
+    options: AnalyzerOptions,
+    frames: Vec<FrameInfo>,
+    /// The distribution of max(R', G', B') over every pixel measured.
+    histogram: Histogram,
+}
+
+impl Analyzer {
+    pub fn new(options: AnalyzerOptions) -> Self {
+        Analyzer {
+            options,
+            ..Analyzer::default()
+        }
+    }
+
+    /// Measures a decoded frame, converting it with `reader` first if its pixel format has no
+    /// native reader.
+    pub fn push_frame(
+        &mut self,
+        reader: &mut FrameReader,
+        frame: usize,
+        time: Option<f64>,
+        video: &Video,
+        range: Option<SignalRange>,
+    ) -> Result<&FrameInfo, FormatError> {
+        let planes = reader.planes(video)?;
+        let frameinfo = self.options.measure(frame, time, &planes, range);
+
+        Ok(self.push(frameinfo))
+    }
+
+    /// Measures a frame's planes.
+    pub fn push_planes(
+        &mut self,
+        frame: usize,
+        time: Option<f64>,
+        planes: &FramePlanes,
+        range: Option<SignalRange>,
+    ) -> &FrameInfo {
+        let frameinfo = self.options.measure(frame, time, planes, range);
+        self.push(frameinfo)
+    }
+
+    /// Adds a frame measured elsewhere, such as by `AnalyzerOptions::measure`. Its histogram
+    /// is merged into the one over every frame, and dropped.
+    pub fn push(&mut self, mut frameinfo: FrameInfo) -> &FrameInfo {
+        if let Some(histogram) = frameinfo.histogram.take() {
+            self.histogram.merge(&histogram);
+        }
+
+        self.frames.push(frameinfo);
+        self.frames.last().unwrap()
+    }
+
+    /// Combines the frames two analyzers measured, keeping the options of `self`.
+    pub fn merge(mut self, other: Self) -> Self {
+        self.frames.extend(other.frames);
+        self.histogram.merge(&other.histogram);
+        self
+    }
+
+    /// The frames measured so far, in the order they were fed in.
+    pub fn frames(&self) -> &[FrameInfo] {
+        &self.frames
+    }
+
+    pub fn finish(mut self) -> Analysis {
+        self.frames.sort_unstable_by_key(|x| x.frame);
+
+        Analysis {
+            frames: self.frames,
+            percentiles: self
+                .options
+                .percentiles
+                .then(|| self.histogram.percentiles()),
+        }
+    }
+}
+
+/// Everything an `Analyzer` measured.
+#[derive(Debug)]
+pub struct Analysis {
+    /// The measured frames, in frame order.
+    pub frames: Vec<FrameInfo>,
+    /// Max(R', G', B') at each of `PERCENTILES` over every pixel of every frame, if
+    /// `AnalyzerOptions::percentiles` is set.
+    pub percentiles: Option<Percentiles>,
+}
+
+impl Analysis {
+    /// Summarises the measured frames, if there were any.
+    pub fn summary(&self) -> Option<Summary> {
+        Summary::new(&self.frames)
+    }
+}
+
+/// Statistics over every measured frame, in nits.
+#[derive(Debug)]
+pub struct Summary {
+    pub frames: usize,
+    pub max_cll: f64,
+    pub max_cll_frame: usize,
+    /// MaxCLL ignoring the brightest few pixels of each frame, which is the same as `max_cll`
+    /// unless `AnalyzerOptions::ignore_brightest` is set.
+    pub robust_max_cll: f64,
+    pub robust_max_cll_frame: usize,
+    pub max_fall: f64,
+    pub max_fall_frame: usize,
+    /// Average of the per-frame maxima.
+    pub avg_max: f64,
+    /// Average of the per-frame average light levels.
+    pub avg_fall: f64,
+    /// The highest per-frame minimum.
+    pub max_min: f64,
+}
+
+impl Summary {
+    /// Summarises the measurements of a sequence of frames, if there are any.
+    pub fn new(results: &[FrameInfo]) -> Option<Self> {
+        let brightest = results.iter().max_by_key(|x| FloatOrd(x.max))?;
+        let robust_brightest = results.iter().max_by_key(|x| FloatOrd(x.robust_max))?;
+        let highest_fall = results.iter().max_by_key(|x| FloatOrd(x.avg))?;
+        let max_min = results.iter().map(|x| FloatOrd(x.min)).max()?.0;
+
+        let mean_nits = |pq: fn(&FrameInfo) -> f64| {
+            results.iter().map(|x| pq_to_nits(pq(x))).sum::<f64>() / results.len() as f64
+        };
+
+        Some(Summary {
+            frames: results.len(),
+            max_cll: pq_to_nits(brightest.max),
+            max_cll_frame: brightest.frame,
+            robust_max_cll: pq_to_nits(robust_brightest.robust_max),
+            robust_max_cll_frame: robust_brightest.frame,
+            max_fall: pq_to_nits(highest_fall.avg),
+            max_fall_frame: highest_fall.frame,
+            avg_max: mean_nits(|x| x.max),
+            avg_fall: mean_nits(|x| x.avg),
+            max_min: pq_to_nits(max_min),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Plane;
+
+    #[test]
+    fn summary() {
+        let results = [
+            FrameInfo {
+                robust_max: 0.0,
+                ..FrameInfo::uniform(0, 1.0)
+            },
+            FrameInfo::uniform(1, 0.0),
+            FrameInfo::uniform(2, 0.0),
+        ];
+        let summary = Summary::new(&results).unwrap();
+
+        assert_eq!(summary.frames, 3);
+        assert_eq!((summary.max_cll, summary.max_cll_frame), (10000.0, 0));
+        assert_eq!(summary.robust_max_cll, 0.0);
+        assert_eq!((summary.max_fall, summary.max_fall_frame), (10000.0, 0));
+        assert!((summary.avg_max - 10000.0 / 3.0).abs() < 1e-9);
+        assert_eq!(summary.max_min, 10000.0);
+    }
+
+    #[test]
+    fn nothing_to_summarise() {
+        assert!(Summary::new(&[]).is_none());
+        assert!(Analyzer::default().finish().summary().is_none());
+    }
+
+    #[test]
+    fn frames_in_order() {
+        let samples: [u16; 4] = [1023, 512, 512, 512];
+        let data: &[u8] = bytemuck::cast_slice(&samples);
+        let plane = || Plane::new(data, 4, 2, 2, 10).unwrap();
+        let planes = FramePlanes::gbr(plane(), plane(), plane(), 10).unwrap();
+
+        let mut analyzer = Analyzer::new(AnalyzerOptions {
+            ignore_brightest: 1,
+            percentiles: true,
+            ..AnalyzerOptions::default()
+        });
+        let frame = analyzer.push_planes(1, None, &planes, None);
+        assert_eq!(frame.max, 1.0);
+        assert_eq!(frame.robust_max, 512.0 / 1023.0);
+        assert!(frame.histogram.is_none());
+        analyzer.push_planes(0, None, &planes, None);
+
+        let analysis = analyzer.finish();
+        let frames: Vec<_> = analysis.frames.iter().map(|x| x.frame).collect();
+        assert_eq!(frames, [0, 1]);
+        assert!(analysis.percentiles.is_some());
+    }
+}
@@ -0,0 +1,30 @@
+/// The exponent of the ITU-R BT.1886 EOTF.
+pub const BT1886_GAMMA: f64 = 2.4;
+
+/// The luminance of reference white on an SDR display, in nits.
+pub const SDR_REFERENCE_WHITE: f64 = 100.0;
+
+/// Converts an SDR signal value to display light, in nits, by the BT.1886 EOTF on a display
+/// with the given reference white and a black level of zero.
+pub fn bt1886_to_nits(signal: f64, reference_white: f64) -> f64 {
+    reference_white * signal.clamp(0.0, 1.0).powf(BT1886_GAMMA)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eotf() {
+        assert_eq!(bt1886_to_nits(0.0, 100.0), 0.0);
+        assert_eq!(bt1886_to_nits(1.0, 100.0), 100.0);
+        assert_eq!(bt1886_to_nits(1.0, 203.0), 203.0);
+        assert!((bt1886_to_nits(0.5, 100.0) - 18.946).abs() < 1e-3);
+    }
+
+    #[test]
+    fn out_of_range_signal_is_clamped() {
+        assert_eq!(bt1886_to_nits(-0.1, 100.0), 0.0);
+        assert_eq!(bt1886_to_nits(1.1, 100.0), 100.0);
+    }
+}
@@ -0,0 +1,725 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use measure_hdr::{
+    SignalRange, Transfer, compare::Tolerances, histogram::PERCENTILES, plot::PlotFormat,
+    scene::SceneDetector,
+};
+
+pub const HELP: &str = "\
+Measure the light levels of an HDR or SDR video and plot them
+
+Usage: measure-hdr [OPTIONS] <INPUT>...
+
+Arguments:
+  <INPUT>...  The video files to measure, overlaid in one plot if there are several
+
+Options:
+  -o, --output <PATH>     Where to write the plot [default: out.<format>]
+  -f, --format <FORMAT>   Plot format: png, jpeg, bmp, svg or pdf [default: from --output, else png]
+      --no-plot           Don't write a plot
+      --csv <PATH>        Write per-frame measurements as CSV
+      --json <PATH>       Write per-frame measurements and a summary as JSON
+      --html <PATH>       Write an interactive HTML report with a zoomable chart and summary
+      --dovi-l1 <PATH>    Write per-shot Dolby Vision Level 1 metadata as a dovi_tool generate config
+      --hdr10plus <PATH>  Write per-scene HDR10+ metadata as hdr10plus_tool JSON
+      --ignore-brightest <N>
+                          Also report MaxCLL ignoring the N brightest pixels of each frame, to
+                          see past hot pixels [default: 0]
+      --plot-percentile <PERCENT>
+                          Also plot a percentile of each frame: 50, 99, 99.9 or 99.99
+      --shots             Print the statistics of every shot
+      --shade-shots       Shade alternate shots in the plot
+      --scene-method <METHOD>
+                          How cuts between shots are detected: avg, a jump in average PQ, or
+                          histogram, a change in the maxRGB histogram [default: avg]
+      --scene-threshold <THRESHOLD>
+                          Smallest change taken as a cut [default: 0.05 for avg, 0.5 for histogram]
+      --encoder-params    Print x265, SVT-AV1 and mkvmerge HDR10 metadata options, the last for
+                          track ID 0
+      --label <LABEL>     Name of an input in the plot legend, given once per input in order
+                          [default: the file name]
+      --offset <FRAMES>   Frames to move an input by, to line it up with the others in the plot
+                          and --compare, given once per input in order [default: 0]
+      --compare           Compare the second of two inputs with the first frame by frame, exiting
+                          with status 1 if any frame is out of tolerance. --csv then writes the
+                          per-frame differences
+      --max-tolerance <TOLERANCE>
+                          Largest difference in a frame's maximum that passes, in nits or as a
+                          percentage such as 5% [default: 5%]
+      --avg-tolerance <TOLERANCE>
+                          Largest difference in a frame's average that passes [default: 5%]
+      --min-tolerance <TOLERANCE>
+                          Largest difference in a frame's minimum that passes [default: 0.01]
+      --top <N>           How many of the most different frames to list [default: 5]
+  -t, --title <TITLE>     Plot title [default: \"SMPTE 2084 PQ Measurements Plot\", or the HLG
+                          or SDR equivalent]
+      --timecode          Label the plot's x-axis with HH:MM:SS:FF timecode rather than frames
+      --size <WxH>        Plot size in pixels [default: 3000x1200]
+  -s, --stream <INDEX>    Index of the video stream to measure [default: the best video stream]
+      --start <POS>       First frame to measure, as a frame number or a time such as 90s or
+                          1:30.5, seeking to it rather than decoding up to it [default: 0]
+      --end <POS>         Stop measuring before this frame or time [default: the end of the stream]
+      --every <N>         Only measure every Nth frame, for a quicker approximate result [default: 1]
+      --keyframes         Only decode and measure keyframes, for a much quicker approximate result
+      --range <RANGE>     Override the signal range: limited or full [default: from the stream]
+      --transfer <TRANSFER>
+                          Override the transfer: pq, hlg or sdr [default: from the stream,
+                          else pq]
+      --hlg-peak <NITS>   Nominal peak of the display HLG is measured on, which also sets the
+                          system gamma [default: 1000]
+      --sdr-white <NITS>  Reference white of the BT.1886 display SDR is measured on [default: 100]
+  -q, --quiet             Only print errors
+  -v, --verbose           Print the measurements of every frame
+  -h, --help              Print help
+  -V, --version           Print version
+
+Exit status:
+  0  Every input was measured, and with --compare every frame is within tolerance
+  1  --compare found frames out of tolerance
+  2  The command line was invalid, or an input could not be measured or an output written
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// A point in a stream, as a frame number or a time in seconds since its start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Frame(usize),
+    Time(f64),
+}
+
+impl FromStr for Position {
+    type Err = ();
+
+    /// Parses a frame number, or a time as seconds with an `s` suffix or as `[[HH:]MM:]SS[.f]`.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let time = if let Some(seconds) = s.strip_suffix('s') {
+            seconds.parse::<f64>().map_err(|_| ())?
+        } else if s.contains(':') && s.matches(':').count() <= 2 {
+            let mut fields = s.rsplit(':');
+            let mut time: f64 = fields.next().ok_or(())?.parse().map_err(|_| ())?;
+            // Otherwise "1:-30" would be 30 seconds
+            if time.is_sign_negative() {
+                return Err(());
+            }
+            for (field, scale) in fields.zip([60.0, 3600.0]) {
+                time += field.parse::<u32>().map_err(|_| ())? as f64 * scale;
+            }
+            time
+        } else {
+            return s.parse().map(Position::Frame).map_err(|_| ());
+        };
+
+        if time.is_finite() && time >= 0.0 {
+            Ok(Position::Time(time))
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// A video file to measure, and how it is shown when several are overlaid.
+#[derive(Debug)]
+pub struct Input {
+    pub path: PathBuf,
+    pub label: String,
+    pub offset: i64,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    /// The inputs to measure, of which there is at least one.
+    pub inputs: Vec<Input>,
+    /// Where to write the plot, if anywhere. The extension always matches the plot format.
+    pub output: Option<PathBuf>,
+    pub format: PlotFormat,
+    pub csv: Option<PathBuf>,
+    pub json: Option<PathBuf>,
+    pub html: Option<PathBuf>,
+    pub dovi_l1: Option<PathBuf>,
+    pub hdr10plus: Option<PathBuf>,
+    pub ignore_brightest: u64,
+    /// Index into `PERCENTILES` of the percentile to plot, if any.
+    pub plot_percentile: Option<usize>,
+    pub shots: bool,
+    pub shade_shots: bool,
+    pub scene_detector: SceneDetector,
+    pub encoder_params: bool,
+    /// The plot title, if not the default for the transfer.
+    pub title: Option<String>,
+    pub size: (u32, u32),
+    pub timecode: bool,
+    pub stream: Option<usize>,
+    pub start: Option<Position>,
+    pub end: Option<Position>,
+    pub every: usize,
+    pub keyframes: bool,
+    pub range: Option<SignalRange>,
+    pub transfer: Option<Transfer>,
+    pub hlg_peak: Option<f64>,
+    pub sdr_white: Option<f64>,
+    /// Whether to compare the second input with the first.
+    pub compare: bool,
+    pub tolerances: Tolerances,
+    pub top: usize,
+    pub verbosity: Verbosity,
+}
+
+impl Args {
+    /// Whether anything asked for needs the input split into shots.
+    pub fn needs_shots(&self) -> bool {
+        self.dovi_l1.is_some() || self.hdr10plus.is_some() || self.shots || self.shade_shots
+    }
+}
+
+/// What the user asked for on the command line.
+pub enum Command {
+    Run(Box<Args>),
+    Help,
+    Version,
+}
+
+#[derive(Debug)]
+pub struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, UsageError> {
+    let value = value.ok_or_else(|| UsageError(format!("{flag} requires a value")))?;
+    value
+        .parse()
+        .map_err(|_| UsageError(format!("invalid value for {flag}: '{value}'")))
+}
+
+fn parse_size(flag: &str, value: Option<String>) -> Result<(u32, u32), UsageError> {
+    let value: String = parse_value(flag, value)?;
+    value
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .ok_or_else(|| UsageError(format!("invalid value for {flag}: '{value}', expected WxH")))
+}
+
+impl Command {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, UsageError> {
+        let mut inputs = Vec::new();
+        let mut labels = Vec::new();
+        let mut offsets = Vec::new();
+        let mut output: Option<PathBuf> = None;
+        let mut format = None;
+        let mut no_plot = false;
+        let mut csv = None;
+        let mut json = None;
+        let mut html = None;
+        let mut dovi_l1 = None;
+        let mut hdr10plus = None;
+        let mut ignore_brightest = 0;
+        let mut plot_percentile = None;
+        let mut shots = false;
+        let mut shade_shots = false;
+        let mut scene_method = None;
+        let mut scene_threshold = None;
+        let mut encoder_params = false;
+        let mut title = None;
+        let mut size = (3000, 1200);
+        let mut timecode = false;
+        let mut stream = None;
+        let mut start = None;
+        let mut end = None;
+        let mut every = 1;
+        let mut keyframes = false;
+        let mut range = None;
+        let mut transfer: Option<String> = None;
+        let mut hlg_peak = None;
+        let mut sdr_white = None;
+        let mut compare = false;
+        let mut tolerances = Tolerances::default();
+        let mut top = 5;
+        let mut verbosity = Verbosity::Normal;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "-o" | "--output" => output = Some(parse_value(&arg, args.next())?),
+                "-f" | "--format" => format = Some(parse_value(&arg, args.next())?),
+                "--no-plot" => no_plot = true,
+                "--csv" => csv = Some(parse_value(&arg, args.next())?),
+                "--json" => json = Some(parse_value(&arg, args.next())?),
+                "--html" => html = Some(parse_value(&arg, args.next())?),
+                "--dovi-l1" => dovi_l1 = Some(parse_value(&arg, args.next())?),
+                "--hdr10plus" => hdr10plus = Some(parse_value(&arg, args.next())?),
+                "--ignore-brightest" => ignore_brightest = parse_value(&arg, args.next())?,
+                "--plot-percentile" => {
+                    let percent: f64 = parse_value(&arg, args.next())?;
+                    let index = PERCENTILES.iter().position(|&x| x == percent);
+                    plot_percentile = Some(index.ok_or_else(|| {
+                        UsageError(format!(
+                            "invalid value for {arg}: '{percent}', expected 50, 99, 99.9 or 99.99"
+                        ))
+                    })?);
+                }
+                "--shots" => shots = true,
+                "--shade-shots" => shade_shots = true,
+                "--scene-method" => scene_method = Some(parse_value(&arg, args.next())?),
+                "--scene-threshold" => scene_threshold = Some(parse_value(&arg, args.next())?),
+                "--encoder-params" => encoder_params = true,
+                "--label" => labels.push(parse_value(&arg, args.next())?),
+                "--offset" => offsets.push(parse_value(&arg, args.next())?),
+                "-t" | "--title" => title = Some(parse_value(&arg, args.next())?),
+                "--size" => size = parse_size(&arg, args.next())?,
+                "--timecode" => timecode = true,
+                "-s" | "--stream" => stream = Some(parse_value(&arg, args.next())?),
+                "--start" => start = Some(parse_value(&arg, args.next())?),
+                "--end" => end = Some(parse_value(&arg, args.next())?),
+                "--every" => every = parse_value(&arg, args.next())?,
+                "--keyframes" => keyframes = true,
+                "--range" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    range = Some(SignalRange::parse(&value).ok_or_else(|| {
+                        UsageError(format!("invalid value for {arg}: '{value}'"))
+                    })?);
+                }
+                "--transfer" => transfer = Some(parse_value(&arg, args.next())?),
+                "--hlg-peak" => hlg_peak = Some(parse_value(&arg, args.next())?),
+                "--sdr-white" => sdr_white = Some(parse_value(&arg, args.next())?),
+                "--compare" => compare = true,
+                "--max-tolerance" => tolerances.max = parse_value(&arg, args.next())?,
+                "--avg-tolerance" => tolerances.avg = parse_value(&arg, args.next())?,
+                "--min-tolerance" => tolerances.min = parse_value(&arg, args.next())?,
+                "--top" => top = parse_value(&arg, args.next())?,
+                "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+                "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+                flag if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(UsageError(format!("unexpected argument '{flag}'")));
+                }
+                _ => inputs.push(PathBuf::from(arg)),
+            }
+        }
+
+        if inputs.is_empty() {
+            return Err(UsageError("no input file given".to_owned()));
+        }
+        if labels.len() > inputs.len() {
+            return Err(UsageError("more --label values than inputs".to_owned()));
+        }
+        if offsets.len() > inputs.len() {
+            return Err(UsageError("more --offset values than inputs".to_owned()));
+        }
+
+        if compare && inputs.len() != 2 {
+            return Err(UsageError("--compare needs exactly two inputs".to_owned()));
+        }
+
+        // What is written or printed per input only makes sense for one
+        let single_input_options = [
+            ("--csv", csv.is_some() && !compare),
+            ("--json", json.is_some()),
+            ("--html", html.is_some()),
+            ("--dovi-l1", dovi_l1.is_some()),
+            ("--hdr10plus", hdr10plus.is_some()),
+            ("--shots", shots),
+            ("--shade-shots", shade_shots),
+            ("--encoder-params", encoder_params),
+        ];
+        if inputs.len() > 1
+            && let Some((flag, _)) = single_input_options.iter().find(|(_, given)| *given)
+        {
+            return Err(UsageError(format!(
+                "{flag} cannot be used with several inputs"
+            )));
+        }
+
+        let mut labels = labels.into_iter();
+        let mut offsets = offsets.into_iter();
+        let inputs = inputs
+            .into_iter()
+            .map(|path| Input {
+                label: labels.next().unwrap_or_else(|| {
+                    path.file_name().map_or_else(
+                        || path.display().to_string(),
+                        |x| x.to_string_lossy().into_owned(),
+                    )
+                }),
+                offset: offsets.next().unwrap_or(0),
+                path,
+            })
+            .collect();
+
+        let end_before_start = match (start, end) {
+            (Some(Position::Frame(start)), Some(Position::Frame(end))) => end <= start,
+            (Some(Position::Time(start)), Some(Position::Time(end))) => end <= start,
+            (None, Some(Position::Frame(end))) => end == 0,
+            (None, Some(Position::Time(end))) => end <= 0.0,
+            _ => false,
+        };
+        if end_before_start {
+            return Err(UsageError("--end must be after --start".to_owned()));
+        }
+        if every == 0 {
+            return Err(UsageError("--every must be at least 1".to_owned()));
+        }
+
+        // Dynamic metadata describes every frame, which sampling would leave out
+        let per_frame_options = [
+            ("--dovi-l1", dovi_l1.is_some()),
+            ("--hdr10plus", hdr10plus.is_some()),
+        ];
+        if (every > 1 || keyframes)
+            && let Some((flag, _)) = per_frame_options.iter().find(|(_, given)| *given)
+        {
+            return Err(UsageError(format!(
+                "{flag} needs every frame, so cannot be used with --every or --keyframes"
+            )));
+        }
+
+        if hlg_peak.is_some_and(|x: f64| !x.is_finite() || x <= 0.0) {
+            return Err(UsageError("--hlg-peak must be above 0".to_owned()));
+        }
+        if sdr_white.is_some_and(|x: f64| !x.is_finite() || x <= 0.0) {
+            return Err(UsageError("--sdr-white must be above 0".to_owned()));
+        }
+        let transfer = match transfer {
+            Some(name) => Some(Transfer::new(&name, hlg_peak, sdr_white).ok_or_else(|| {
+                UsageError(format!(
+                    "invalid value for --transfer: '{name}', expected pq, hlg or sdr"
+                ))
+            })?),
+            None => None,
+        };
+
+        let scene_method: String = scene_method.unwrap_or_else(|| "avg".to_owned());
+        let scene_detector =
+            SceneDetector::new(&scene_method, scene_threshold).ok_or_else(|| {
+                UsageError(format!(
+                    "invalid value for --scene-method: '{scene_method}', expected avg or histogram"
+                ))
+            })?;
+
+        let format = match output.as_ref() {
+            Some(output) => {
+                let output_format = output
+                    .extension()
+                    .and_then(|extension| PlotFormat::from_extension(&extension.to_string_lossy()))
+                    .ok_or_else(|| {
+                        UsageError(format!(
+                            "cannot tell the plot format of '{}', use a png, jpg, bmp, svg or pdf extension",
+                            output.display()
+                        ))
+                    })?;
+                if format.is_some_and(|format| format != output_format) {
+                    return Err(UsageError(
+                        "--format does not match the extension of --output".to_owned(),
+                    ));
+                }
+                output_format
+            }
+            None => format.unwrap_or(PlotFormat::Png),
+        };
+        let output =
+            output.unwrap_or_else(|| PathBuf::from("out").with_extension(format.extension()));
+        let output = (!no_plot).then_some(output);
+
+        Ok(Command::Run(Box::new(Args {
+            inputs,
+            output,
+            format,
+            csv,
+            json,
+            html,
+            dovi_l1,
+            hdr10plus,
+            ignore_brightest,
+            plot_percentile,
+            shots,
+            shade_shots,
+            scene_detector,
+            encoder_params,
+            title,
+            size,
+            timecode,
+            stream,
+            start,
+            end,
+            every,
+            keyframes,
+            range,
+            transfer,
+            hlg_peak,
+            sdr_white,
+            compare,
+            tolerances,
+            top,
+            verbosity,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use measure_hdr::compare::Tolerance;
+
+    fn parse(args: &[&str]) -> Result<Command, UsageError> {
+        Command::parse(args.iter().map(|x| x.to_string()))
+    }
+
+    fn run(args: &[&str]) -> Args {
+        match parse(args) {
+            Ok(Command::Run(args)) => *args,
+            Ok(_) => panic!("{args:?} did not ask to run"),
+            Err(e) => panic!("{args:?} failed to parse: {e}"),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(_) => panic!("{args:?} parsed"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let args = run(&["in.mkv"]);
+
+        assert_eq!(args.inputs.len(), 1);
+        assert_eq!(args.inputs[0].label, "in.mkv");
+        assert_eq!(args.output, Some(PathBuf::from("out.png")));
+        assert_eq!(args.format, PlotFormat::Png);
+        assert_eq!(args.size, (3000, 1200));
+        assert_eq!(args.every, 1);
+        assert_eq!(args.verbosity, Verbosity::Normal);
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert!(matches!(parse(&["in.mkv", "--help"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+    }
+
+    #[test]
+    fn output_format() {
+        assert_eq!(run(&["in.mkv", "-o", "plot.svg"]).format, PlotFormat::Svg);
+        assert_eq!(
+            run(&["in.mkv", "-f", "pdf"]).output,
+            Some(PathBuf::from("out.pdf"))
+        );
+        assert_eq!(run(&["in.mkv", "--no-plot"]).output, None);
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(error(&[]), "no input file given");
+        assert_eq!(
+            error(&["in.mkv", "--bogus"]),
+            "unexpected argument '--bogus'"
+        );
+        assert_eq!(error(&["in.mkv", "--title"]), "--title requires a value");
+        assert_eq!(
+            error(&["in.mkv", "--ignore-brightest", "some"]),
+            "invalid value for --ignore-brightest: 'some'"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--size", "3000"]),
+            "invalid value for --size: '3000', expected WxH"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--size", "0x1200"]),
+            "invalid value for --size: '0x1200', expected WxH"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--range", "auto"]),
+            "invalid value for --range: 'auto'"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--plot-percentile", "90"]),
+            "invalid value for --plot-percentile: '90', expected 50, 99, 99.9 or 99.99"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--scene-method", "ai"]),
+            "invalid value for --scene-method: 'ai', expected avg or histogram"
+        );
+    }
+
+    #[test]
+    fn plot_format_errors() {
+        assert_eq!(
+            error(&["in.mkv", "-o", "plot.gif"]),
+            "cannot tell the plot format of 'plot.gif', use a png, jpg, bmp, svg or pdf extension"
+        );
+        assert_eq!(
+            error(&["in.mkv", "-o", "plot.png", "-f", "svg"]),
+            "--format does not match the extension of --output"
+        );
+    }
+
+    #[test]
+    fn position() {
+        assert_eq!("120".parse(), Ok(Position::Frame(120)));
+        assert_eq!("2.5s".parse(), Ok(Position::Time(2.5)));
+        assert_eq!("01:30".parse(), Ok(Position::Time(90.0)));
+        assert_eq!("1:02:03.5".parse(), Ok(Position::Time(3723.5)));
+    }
+
+    #[test]
+    fn invalid_position() {
+        for position in [
+            "", "-5", "1.5", "-2s", "infs", "1:2:3:4", "a:30", "1:-30", "s",
+        ] {
+            assert_eq!(position.parse::<Position>(), Err(()), "{position}");
+        }
+    }
+
+    #[test]
+    fn range_and_sampling() {
+        let args = run(&["in.mkv", "--start", "10", "--end", "1:00", "--every", "4"]);
+
+        assert_eq!(args.start, Some(Position::Frame(10)));
+        assert_eq!(args.end, Some(Position::Time(60.0)));
+        assert_eq!(args.every, 4);
+    }
+
+    #[test]
+    fn range_and_sampling_errors() {
+        assert_eq!(
+            error(&["in.mkv", "--start", "100", "--end", "50"]),
+            "--end must be after --start"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--start", "5s", "--end", "5s"]),
+            "--end must be after --start"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--end", "0"]),
+            "--end must be after --start"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--start", "1:xx"]),
+            "invalid value for --start: '1:xx'"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--every", "0"]),
+            "--every must be at least 1"
+        );
+    }
+
+    #[test]
+    fn labels_and_offsets() {
+        let args = run(&[
+            "a/master.mkv",
+            "b/encode.mkv",
+            "c/proxy.mp4",
+            "--label",
+            "Master",
+            "--offset",
+            "0",
+            "--offset",
+            "-24",
+            "--no-plot",
+        ]);
+
+        let inputs: Vec<_> = args
+            .inputs
+            .iter()
+            .map(|x| (x.label.as_str(), x.offset))
+            .collect();
+        assert_eq!(
+            inputs,
+            [("Master", 0), ("encode.mkv", -24), ("proxy.mp4", 0)]
+        );
+    }
+
+    #[test]
+    fn labels_and_offsets_errors() {
+        assert_eq!(
+            error(&["in.mkv", "--label", "a", "--label", "b"]),
+            "more --label values than inputs"
+        );
+        assert_eq!(
+            error(&[
+                "a.mkv", "b.mkv", "--offset", "1", "--offset", "2", "--offset", "3"
+            ]),
+            "more --offset values than inputs"
+        );
+        assert_eq!(
+            error(&["a.mkv", "b.mkv", "--offset", "1.5"]),
+            "invalid value for --offset: '1.5'"
+        );
+        assert_eq!(
+            error(&["a.mkv", "b.mkv", "--label"]),
+            "--label requires a value"
+        );
+    }
+
+    #[test]
+    fn compare() {
+        let args = run(&[
+            "master.mkv",
+            "encode.mkv",
+            "--compare",
+            "--max-tolerance",
+            "10",
+            "--min-tolerance",
+            "1%",
+            "--csv",
+            "deltas.csv",
+        ]);
+
+        assert!(args.compare);
+        assert_eq!(args.tolerances.max, Tolerance::Nits(10.0));
+        assert_eq!(args.tolerances.avg, Tolerance::Percent(5.0));
+        assert_eq!(args.tolerances.min, Tolerance::Percent(1.0));
+        assert_eq!(args.csv, Some(PathBuf::from("deltas.csv")));
+    }
+
+    #[test]
+    fn compare_errors() {
+        assert_eq!(
+            error(&["in.mkv", "--compare"]),
+            "--compare needs exactly two inputs"
+        );
+        assert_eq!(
+            error(&["a.mkv", "b.mkv", "c.mkv", "--compare"]),
+            "--compare needs exactly two inputs"
+        );
+        assert_eq!(
+            error(&["a.mkv", "b.mkv", "--compare", "--max-tolerance", "5 nits"]),
+            "invalid value for --max-tolerance: '5 nits'"
+        );
+        assert_eq!(
+            error(&["a.mkv", "b.mkv", "--compare", "--avg-tolerance", "-1%"]),
+            "invalid value for --avg-tolerance: '-1%'"
+        );
+        // Without --compare, --csv writes per-frame measurements, which only makes sense for one
+        assert_eq!(
+            error(&["a.mkv", "b.mkv", "--csv", "out.csv"]),
+            "--csv cannot be used with several inputs"
+        );
+    }
+
+    #[test]
+    fn dynamic_metadata_needs_every_frame() {
+        assert_eq!(
+            error(&["in.mkv", "--dovi-l1", "l1.json", "--every", "2"]),
+            "--dovi-l1 needs every frame, so cannot be used with --every or --keyframes"
+        );
+        assert_eq!(
+            error(&["in.mkv", "--hdr10plus", "hdr10plus.json", "--keyframes"]),
+            "--hdr10plus needs every frame, so cannot be used with --every or --keyframes"
+        );
+        assert!(run(&["in.mkv", "--dovi-l1", "l1.json", "--every", "1"]).needs_shots());
+    }
+}
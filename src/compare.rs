@@ -0,0 +1,391 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use crate::{FrameInfo, pq_to_nits};
+
+/// How far a measurement may differ from the reference's and still pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    /// An absolute difference, in nits.
+    Nits(f64),
+    /// A difference relative to the reference's measurement, in percent. So that near-black
+    /// frames aren't held to a vanishingly small difference, it is never less than the same
+    /// percentage of `RELATIVE_FLOOR_NITS`.
+    Percent(f64),
+}
+
+/// The smallest reference value relative tolerances are taken of, in nits.
+pub const RELATIVE_FLOOR_NITS: f64 = 1.0;
+
+impl Tolerance {
+    /// Whether `delta` is within the tolerance of a `reference` measurement, both in nits.
+    pub fn allows(self, reference: f64, delta: f64) -> bool {
+        let limit = match self {
+            Tolerance::Nits(nits) => nits,
+            Tolerance::Percent(percent) => reference.max(RELATIVE_FLOOR_NITS) * percent / 100.0,
+        };
+        delta.abs() <= limit
+    }
+}
+
+impl FromStr for Tolerance {
+    type Err = ();
+
+    /// Parses a number of nits, or a percentage with a `%` suffix.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (value, percent) = match s.strip_suffix('%') {
+            Some(value) => (value, true),
+            None => (s, false),
+        };
+        let value: f64 = value.parse().map_err(|_| ())?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(());
+        }
+
+        Ok(if percent {
+            Tolerance::Percent(value)
+        } else {
+            Tolerance::Nits(value)
+        })
+    }
+}
+
+impl fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tolerance::Nits(nits) => write!(f, "{nits} nits"),
+            Tolerance::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+/// One of the per-frame measurements compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Max,
+    Avg,
+    Min,
+}
+
+impl Level {
+    pub const ALL: [Level; 3] = [Level::Max, Level::Avg, Level::Min];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Max => "maximum",
+            Level::Avg => "average",
+            Level::Min => "minimum",
+        }
+    }
+}
+
+/// The tolerances of each per-frame measurement.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerances {
+    /// Catches clipped or boosted highlights.
+    pub max: Tolerance,
+    pub avg: Tolerance,
+    /// Catches raised or crushed blacks.
+    pub min: Tolerance,
+}
+
+impl Tolerances {
+    pub fn get(&self, level: Level) -> Tolerance {
+        match level {
+            Level::Max => self.max,
+            Level::Avg => self.avg,
+            Level::Min => self.min,
+        }
+    }
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Tolerances {
+            max: Tolerance::Percent(5.0),
+            avg: Tolerance::Percent(5.0),
+            min: Tolerance::Nits(0.01),
+        }
+    }
+}
+
+/// A frame's maximum, average and minimum, in nits.
+#[derive(Debug, Clone, Copy)]
+pub struct Levels {
+    pub max: f64,
+    pub avg: f64,
+    pub min: f64,
+}
+
+impl Levels {
+    fn of(frameinfo: &FrameInfo) -> Self {
+        Levels {
+            max: pq_to_nits(frameinfo.max),
+            avg: pq_to_nits(frameinfo.avg),
+            min: pq_to_nits(frameinfo.min),
+        }
+    }
+
+    pub fn get(&self, level: Level) -> f64 {
+        match level {
+            Level::Max => self.max,
+            Level::Avg => self.avg,
+            Level::Min => self.min,
+        }
+    }
+}
+
+/// How a frame of the other input differs from the reference frame it is aligned with.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameDelta {
+    /// The frame number in the reference.
+    pub frame: usize,
+    /// The frame number in the other input.
+    pub other_frame: usize,
+    pub reference: Levels,
+    pub other: Levels,
+}
+
+impl FrameDelta {
+    /// The other input's measurement less the reference's, in nits.
+    pub fn delta(&self, level: Level) -> f64 {
+        self.other.get(level) - self.reference.get(level)
+    }
+
+    /// Whether every measurement is within its tolerance.
+    pub fn passes(&self, tolerances: &Tolerances) -> bool {
+        Level::ALL.into_iter().all(|level| {
+            tolerances
+                .get(level)
+                .allows(self.reference.get(level), self.delta(level))
+        })
+    }
+}
+
+/// The frame-by-frame differences between two measured inputs, such as a master and its encode.
+#[derive(Debug)]
+pub struct Comparison {
+    /// One per aligned pair of frames, in reference frame order.
+    pub deltas: Vec<FrameDelta>,
+    /// How many reference frame numbers have no frame of the other input aligned with them.
+    pub reference_only: usize,
+    /// How many frame numbers of the other input have no reference frame aligned with them.
+    pub other_only: usize,
+}
+
+impl Comparison {
+    /// Aligns the frames of `other` with those of `reference`, frame `n` of `other` with frame
+    /// `n + offset` of `reference`. Where an input has several frames with the same number,
+    /// only the first is compared.
+    pub fn new(reference: &[FrameInfo], other: &[FrameInfo], offset: i64) -> Self {
+        let mut other_by_frame: HashMap<i64, &FrameInfo> = HashMap::new();
+        for x in other {
+            other_by_frame.entry(x.frame as i64 + offset).or_insert(x);
+        }
+
+        let mut reference_frames = HashSet::new();
+        let deltas: Vec<_> = reference
+            .iter()
+            .filter(|x| reference_frames.insert(x.frame))
+            .filter_map(|x| {
+                let aligned = other_by_frame.get(&(x.frame as i64))?;
+                Some(FrameDelta {
+                    frame: x.frame,
+                    other_frame: aligned.frame,
+                    reference: Levels::of(x),
+                    other: Levels::of(aligned),
+                })
+            })
+            .collect();
+
+        Comparison {
+            reference_only: reference_frames.len() - deltas.len(),
+            other_only: other_by_frame.len() - deltas.len(),
+            deltas,
+        }
+    }
+
+    /// The frames outside the tolerances.
+    pub fn failures(&self, tolerances: &Tolerances) -> Vec<&FrameDelta> {
+        self.deltas
+            .iter()
+            .filter(|x| !x.passes(tolerances))
+            .collect()
+    }
+
+    /// The `count` frames whose measurement differs the most, most first.
+    pub fn largest(&self, count: usize, level: Level) -> Vec<&FrameDelta> {
+        let mut deltas: Vec<_> = self.deltas.iter().collect();
+        deltas.sort_by(|a, b| {
+            let (a, b) = (a.delta(level).abs(), b.delta(level).abs());
+            b.total_cmp(&a)
+        });
+        deltas.truncate(count);
+        deltas
+    }
+
+    /// The mean difference in a measurement over every aligned frame, in nits, if any frames
+    /// are aligned.
+    pub fn mean(&self, level: Level) -> Option<f64> {
+        if self.deltas.is_empty() {
+            return None;
+        }
+
+        let sum: f64 = self.deltas.iter().map(|x| x.delta(level)).sum();
+        Some(sum / self.deltas.len() as f64)
+    }
+}
+
+/// Writes one row per aligned pair of frames, with both inputs' measurements and their
+/// differences in nits, and whether the frame is within `tolerances`.
+pub fn write_csv(
+    comparison: &Comparison,
+    tolerances: &Tolerances,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "frame,other_frame,max_nits,other_max_nits,max_delta,avg_nits,other_avg_nits,avg_delta,\
+         min_nits,other_min_nits,min_delta,pass"
+    )?;
+
+    for x in &comparison.deltas {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            x.frame,
+            x.other_frame,
+            x.reference.max,
+            x.other.max,
+            x.delta(Level::Max),
+            x.reference.avg,
+            x.other.avg,
+            x.delta(Level::Avg),
+            x.reference.min,
+            x.other.min,
+            x.delta(Level::Min),
+            x.passes(tolerances),
+        )?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nits_to_pq;
+
+    /// Frames `0..levels.len()` with the given maximum, in nits, and an average and minimum of
+    /// zero.
+    fn frames(levels: &[f64]) -> Vec<FrameInfo> {
+        let frames = levels.iter().enumerate();
+        frames
+            .map(|(frame, &nits)| FrameInfo {
+                max: nits_to_pq(nits),
+                ..FrameInfo::uniform(frame, 0.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tolerance_from_str() {
+        assert_eq!("0.5".parse(), Ok(Tolerance::Nits(0.5)));
+        assert_eq!("5%".parse(), Ok(Tolerance::Percent(5.0)));
+        assert_eq!("0".parse(), Ok(Tolerance::Nits(0.0)));
+
+        for invalid in ["", "%", "-1", "-5%", "5 %", "inf", "NaN%", "five"] {
+            assert_eq!(invalid.parse::<Tolerance>(), Err(()), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn tolerance_allows() {
+        assert!(Tolerance::Nits(1.0).allows(1000.0, -1.0));
+        assert!(!Tolerance::Nits(1.0).allows(1000.0, 1.5));
+
+        assert!(Tolerance::Percent(5.0).allows(1000.0, 50.0));
+        assert!(!Tolerance::Percent(5.0).allows(1000.0, -51.0));
+
+        // Relative to at least 1 nit
+        assert!(Tolerance::Percent(5.0).allows(0.001, 0.05));
+        assert!(!Tolerance::Percent(5.0).allows(0.001, 0.06));
+    }
+
+    #[test]
+    fn alignment() {
+        let reference = frames(&[100.0, 200.0, 300.0, 400.0, 500.0]);
+        let other = frames(&[300.0, 400.0, 500.0, 600.0]);
+
+        let comparison = Comparison::new(&reference, &other, 2);
+        let pairs: Vec<_> = comparison
+            .deltas
+            .iter()
+            .map(|x| (x.frame, x.other_frame))
+            .collect();
+        assert_eq!(pairs, [(2, 0), (3, 1), (4, 2)]);
+        assert_eq!(comparison.reference_only, 2);
+        assert_eq!(comparison.other_only, 1);
+        assert!(comparison.mean(Level::Max).unwrap().abs() < 1e-6);
+
+        // A negative offset moves the other input later
+        let comparison = Comparison::new(&other, &reference, -2);
+        assert_eq!(comparison.deltas.len(), 3);
+        assert_eq!(comparison.deltas[0].other_frame, 2);
+    }
+
+    #[test]
+    fn duplicate_frames() {
+        let mut reference = frames(&[100.0, 200.0, 300.0]);
+        reference.push(FrameInfo {
+            max: nits_to_pq(1000.0),
+            ..FrameInfo::uniform(1, 0.0)
+        });
+        let mut other = frames(&[100.0, 200.0]);
+        other.push(FrameInfo::uniform(0, 0.0));
+
+        let comparison = Comparison::new(&reference, &other, 0);
+        let frames: Vec<_> = comparison.deltas.iter().map(|x| x.frame).collect();
+        assert_eq!(frames, [0, 1]);
+        assert_eq!(comparison.reference_only, 1);
+        assert_eq!(comparison.other_only, 0);
+        assert!(comparison.mean(Level::Max).unwrap().abs() < 1e-6);
+    }
+
+    #[test]
+    fn nothing_aligned() {
+        let comparison = Comparison::new(&frames(&[100.0]), &frames(&[100.0]), 1);
+
+        assert!(comparison.deltas.is_empty());
+        assert_eq!((comparison.reference_only, comparison.other_only), (1, 1));
+        assert_eq!(comparison.mean(Level::Avg), None);
+        assert!(comparison.largest(5, Level::Max).is_empty());
+    }
+
+    #[test]
+    fn largest_and_failures() {
+        let reference = frames(&[100.0, 100.0, 100.0, 100.0]);
+        let other = frames(&[101.0, 80.0, 100.0, 110.0]);
+        let comparison = Comparison::new(&reference, &other, 0);
+
+        let largest: Vec<_> = comparison
+            .largest(2, Level::Max)
+            .iter()
+            .map(|x| x.frame)
+            .collect();
+        assert_eq!(largest, [1, 3]);
+        assert_eq!(comparison.largest(10, Level::Max).len(), 4);
+
+        let failures: Vec<_> = comparison
+            .failures(&Tolerances::default())
+            .iter()
+            .map(|x| x.frame)
+            .collect();
+        assert_eq!(failures, [1, 3]);
+        assert!((comparison.mean(Level::Max).unwrap() - -2.25).abs() < 1e-6);
+    }
+}
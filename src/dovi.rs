@@ -0,0 +1,188 @@
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::{
+    FrameInfo, Summary,
+    metadata::{ContentLightLevel, StaticMetadata},
+    scene::shot_frames,
+};
+
+/// Converts a PQ signal value to the 12-bit code values Dolby Vision metadata uses.
+fn pq_to_12bit(pq: f64) -> u16 {
+    (pq.clamp(0.0, 1.0) * 4095.0).round() as u16
+}
+
+/// Writes a dovi_tool `generate` config with Level 1 metadata for every shot.
+///
+/// Level 6 (the HDR10 fallback metadata) is included when the mastering display's luminance is
+/// known, with the measured MaxCLL and MaxFALL. Nothing is written if a shot is empty or runs
+/// past `results`.
+pub fn write_generator_config(
+    results: &[FrameInfo],
+    shots: &[Range<usize>],
+    summary: &Summary,
+    metadata: &StaticMetadata,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let frames_per_shot = shots
+        .iter()
+        .map(|shot| shot_frames(results, shot))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"cm_version\": \"V40\",")?;
+    writeln!(writer, "  \"length\": {},", results.len())?;
+
+    if let Some((min, max)) = metadata.mastering_display.and_then(|x| x.luminance) {
+        let cll = ContentLightLevel::from_summary(summary);
+
+        writeln!(writer, "  \"level6\": {{")?;
+        writeln!(
+            writer,
+            "    \"max_display_mastering_luminance\": {},",
+            max.round()
+        )?;
+        // In units of 0.0001 nits
+        writeln!(
+            writer,
+            "    \"min_display_mastering_luminance\": {},",
+            (min * 10000.0).round()
+        )?;
+        writeln!(writer, "    \"max_content_light_level\": {},", cll.max_cll)?;
+        writeln!(
+            writer,
+            "    \"max_frame_average_light_level\": {}",
+            cll.max_fall
+        )?;
+        writeln!(writer, "  }},")?;
+    }
+
+    writeln!(writer, "  \"shots\": [")?;
+
+    for (i, (shot, frames)) in shots.iter().zip(frames_per_shot).enumerate() {
+        let min = frames.iter().map(|x| x.min).fold(f64::INFINITY, f64::min);
+        let max = frames
+            .iter()
+            .map(|x| x.max)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let avg = frames.iter().map(|x| x.avg_signal).sum::<f64>() / frames.len() as f64;
+
+        let separator = if i + 1 < shots.len() { "," } else { "" };
+
+        writeln!(writer, "    {{")?;
+        writeln!(writer, "      \"start\": {},", shot.start)?;
+        writeln!(writer, "      \"duration\": {},", shot.len())?;
+        writeln!(writer, "      \"metadata_blocks\": [")?;
+        writeln!(
+            writer,
+            "        {{\"Level1\": {{\"min_pq\": {}, \"max_pq\": {}, \"avg_pq\": {}}}}}",
+            pq_to_12bit(min),
+            pq_to_12bit(max),
+            pq_to_12bit(avg),
+        )?;
+        writeln!(writer, "      ]")?;
+        writeln!(writer, "    }}{separator}")?;
+    }
+
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::MasteringDisplay;
+
+    fn config(results: &[FrameInfo], shots: &[Range<usize>], metadata: &StaticMetadata) -> String {
+        let summary = Summary::new(results).unwrap();
+        let mut output = Vec::new();
+        write_generator_config(results, shots, &summary, metadata, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn pq_codes() {
+        assert_eq!(pq_to_12bit(0.0), 0);
+        assert_eq!(pq_to_12bit(0.5), 2048);
+        assert_eq!(pq_to_12bit(1.0), 4095);
+        assert_eq!(pq_to_12bit(1.5), 4095);
+    }
+
+    #[test]
+    fn level1_per_shot() {
+        let results = [0.2, 0.2, 0.5, 0.6].map(|pq| FrameInfo::uniform(0, pq));
+
+        assert_eq!(
+            config(&results, &[0..2, 2..4], &StaticMetadata::default()),
+            r#"{
+  "cm_version": "V40",
+  "length": 4,
+  "shots": [
+    {
+      "start": 0,
+      "duration": 2,
+      "metadata_blocks": [
+        {"Level1": {"min_pq": 819, "max_pq": 819, "avg_pq": 819}}
+      ]
+    },
+    {
+      "start": 2,
+      "duration": 2,
+      "metadata_blocks": [
+        {"Level1": {"min_pq": 2048, "max_pq": 2457, "avg_pq": 2252}}
+      ]
+    }
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn invalid_shots() {
+        let results = [FrameInfo::uniform(0, 0.2), FrameInfo::uniform(1, 0.5)];
+        let summary = Summary::new(&results).unwrap();
+
+        for shots in [
+            vec![0..1, 1..1],
+            vec![0..1, 1..3],
+            vec![0..1, Range { start: 2, end: 1 }],
+        ] {
+            let mut output = Vec::new();
+            let metadata = StaticMetadata::default();
+            let error = write_generator_config(&results, &shots, &summary, &metadata, &mut output)
+                .unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{shots:?}");
+            assert!(output.is_empty());
+        }
+    }
+
+    #[test]
+    fn level6_from_mastering_display() {
+        let results = [FrameInfo::uniform(0, 0.5), FrameInfo::uniform(1, 0.4)];
+        let metadata = StaticMetadata {
+            content_light_level: None,
+            mastering_display: Some(MasteringDisplay {
+                primaries: None,
+                white_point: None,
+                luminance: Some((0.005, 1000.0)),
+            }),
+        };
+
+        let config = config(&results, &[0..1, 1..2], &metadata);
+        assert!(config.contains(
+            r#"  "level6": {
+    "max_display_mastering_luminance": 1000,
+    "min_display_mastering_luminance": 50,
+    "max_content_light_level": 93,
+    "max_frame_average_light_level": 93
+  },
+"#
+        ));
+    }
+}
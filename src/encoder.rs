@@ -0,0 +1,158 @@
+use crate::metadata::{Chromaticity, ContentLightLevel, MasteringDisplay};
+
+/// HDR10 static metadata ready to be handed to an encoder or muxer.
+pub struct EncoderParams {
+    pub content_light_level: ContentLightLevel,
+    pub mastering_display: Option<MasteringDisplay>,
+}
+
+impl EncoderParams {
+    /// x265's `--max-cll` and `--master-display`. The latter takes chromaticities in units of
+    /// 0.00002 and luminances in units of 0.0001 nits, in G, B, R order.
+    pub fn x265(&self) -> String {
+        let cll = self.content_light_level;
+        let mut params = format!("--max-cll \"{},{}\"", cll.max_cll, cll.max_fall);
+
+        if let Some((primaries, white_point, (min, max))) = self.complete_mastering_display() {
+            let xy = |[x, y]: Chromaticity| {
+                format!("({},{})", (x * 50000.0).round(), (y * 50000.0).round())
+            };
+            let [r, g, b] = primaries;
+
+            params += &format!(
+                " --master-display \"G{}B{}R{}WP{}L({},{})\"",
+                xy(g),
+                xy(b),
+                xy(r),
+                xy(white_point),
+                (max * 10000.0).round(),
+                (min * 10000.0).round(),
+            );
+        }
+
+        params
+    }
+
+    /// SVT-AV1's `--content-light` and `--mastering-display`, which take plain decimal values.
+    /// The latter is quoted like x265's, as shells treat its parentheses specially.
+    pub fn svt_av1(&self) -> String {
+        let cll = self.content_light_level;
+        let mut params = format!("--content-light {},{}", cll.max_cll, cll.max_fall);
+
+        if let Some((primaries, white_point, (min, max))) = self.complete_mastering_display() {
+            let xy = |[x, y]: Chromaticity| format!("({x:.4},{y:.4})");
+            let [r, g, b] = primaries;
+
+            params += &format!(
+                " --mastering-display \"G{}B{}R{}WP{}L({max:.4},{min:.4})\"",
+                xy(g),
+                xy(b),
+                xy(r),
+                xy(white_point),
+            );
+        }
+
+        params
+    }
+
+    /// mkvmerge's per-track colour options, for the track with ID `track` in the file being
+    /// muxed.
+    pub fn mkvmerge(&self, track: u64) -> String {
+        let cll = self.content_light_level;
+        let mut params = format!(
+            "--max-content-light {track}:{} --max-frame-light {track}:{}",
+            cll.max_cll, cll.max_fall
+        );
+
+        if let Some((primaries, white_point, (min, max))) = self.complete_mastering_display() {
+            let [r, g, b] = primaries;
+
+            params += &format!(
+                " --chromaticity-coordinates {track}:{},{},{},{},{},{} \
+                 --white-colour-coordinates {track}:{},{} \
+                 --max-luminance {track}:{max} --min-luminance {track}:{min}",
+                r[0], r[1], g[0], g[1], b[0], b[1], white_point[0], white_point[1],
+            );
+        }
+
+        params
+    }
+
+    /// The mastering display, if it declares everything the encoders need.
+    fn complete_mastering_display(&self) -> Option<([Chromaticity; 3], Chromaticity, (f64, f64))> {
+        let mastering_display = self.mastering_display?;
+
+        Some((
+            mastering_display.primaries?,
+            mastering_display.white_point?,
+            mastering_display.luminance?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bt2020_params() -> EncoderParams {
+        EncoderParams {
+            content_light_level: ContentLightLevel {
+                max_cll: 1000,
+                max_fall: 400,
+            },
+            mastering_display: Some(MasteringDisplay {
+                primaries: Some([[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]]),
+                white_point: Some([0.3127, 0.3290]),
+                luminance: Some((0.0001, 1000.0)),
+            }),
+        }
+    }
+
+    #[test]
+    fn x265() {
+        assert_eq!(
+            bt2020_params().x265(),
+            "--max-cll \"1000,400\" --master-display \
+             \"G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,1)\""
+        );
+    }
+
+    #[test]
+    fn svt_av1() {
+        assert_eq!(
+            bt2020_params().svt_av1(),
+            "--content-light 1000,400 --mastering-display \
+             \"G(0.1700,0.7970)B(0.1310,0.0460)R(0.7080,0.2920)WP(0.3127,0.3290)L(1000.0000,0.0001)\""
+        );
+    }
+
+    #[test]
+    fn mkvmerge() {
+        assert_eq!(
+            bt2020_params().mkvmerge(1),
+            "--max-content-light 1:1000 --max-frame-light 1:400 \
+             --chromaticity-coordinates 1:0.708,0.292,0.17,0.797,0.131,0.046 \
+             --white-colour-coordinates 1:0.3127,0.329 \
+             --max-luminance 1:1000 --min-luminance 1:0.0001"
+        );
+    }
+
+    #[test]
+    fn incomplete_mastering_display_is_left_out() {
+        let params = EncoderParams {
+            mastering_display: Some(MasteringDisplay {
+                primaries: None,
+                white_point: None,
+                luminance: Some((0.0001, 1000.0)),
+            }),
+            ..bt2020_params()
+        };
+
+        assert_eq!(params.x265(), "--max-cll \"1000,400\"");
+        assert_eq!(params.svt_av1(), "--content-light 1000,400");
+        assert_eq!(
+            params.mkvmerge(0),
+            "--max-content-light 0:1000 --max-frame-light 0:400"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::{self, Write};
+
+use crate::{
+    FrameInfo, Summary,
+    histogram::{PERCENTILES, Percentiles},
+    pq_to_nits,
+};
+
+/// Formats a time in seconds as `HH:MM:SS.mmm`.
+pub fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (secs, millis) = (millis / 1000, millis % 1000);
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        millis
+    )
+}
+
+/// A name for one of `PERCENTILES` that is usable as a column or key, such as "p99_9".
+fn percentile_key(percent: f64) -> String {
+    if percent == 50.0 {
+        "median".to_owned()
+    } else {
+        format!("p{}", percent.to_string().replace('.', "_"))
+    }
+}
+
+/// Formats a time in seconds as `HH:MM:SS:FF` non-drop-frame timecode, counting frames at the
+/// nominal (integer) rate, as editing software does for rates such as 23.976.
+pub fn format_timecode(seconds: f64, fps: f64) -> String {
+    let nominal = fps.round().max(1.0) as u64;
+    let frames = (seconds.max(0.0) * fps).round() as u64;
+    let secs = frames / nominal;
+
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        frames % nominal
+    )
+}
+
+/// Writes one row per frame, with each measurement as both a PQ signal value and in nits.
+/// Percentiles follow in nits, left empty if they weren't measured.
+pub fn write_csv(results: &[FrameInfo], mut writer: impl Write) -> io::Result<()> {
+    write!(
+        writer,
+        "frame,time,timecode,min_pq,avg_pq,max_pq,robust_max_pq,\
+         min_nits,avg_nits,max_nits,robust_max_nits"
+    )?;
+    for percent in PERCENTILES {
+        write!(writer, ",{}_nits", percentile_key(percent))?;
+    }
+    writeln!(writer)?;
+
+    for x in results {
+        let (time, timecode) = match x.time {
+            Some(time) => (time.to_string(), format_timestamp(time)),
+            None => (String::new(), String::new()),
+        };
+
+        write!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            x.frame,
+            time,
+            timecode,
+            x.min,
+            x.avg,
+            x.max,
+            x.robust_max,
+            pq_to_nits(x.min),
+            pq_to_nits(x.avg),
+            pq_to_nits(x.max),
+            pq_to_nits(x.robust_max),
+        )?;
+        match x.percentiles {
+            Some(percentiles) => {
+                for pq in percentiles {
+                    write!(writer, ",{}", pq_to_nits(pq))?;
+                }
+            }
+            None => write!(writer, "{}", ",".repeat(PERCENTILES.len()))?,
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+/// Formats an optional value as JSON, with `None` as `null`.
+fn json_or_null<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_owned(), |x| x.to_string())
+}
+
+/// Formats percentiles as a JSON object in nits, or `null` if they weren't measured.
+fn json_percentiles(percentiles: Option<&Percentiles>) -> String {
+    let Some(percentiles) = percentiles else {
+        return "null".to_owned();
+    };
+
+    let members = PERCENTILES
+        .iter()
+        .zip(percentiles)
+        .map(|(&percent, &pq)| format!("\"{}\": {}", percentile_key(percent), pq_to_nits(pq)))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", members.join(", "))
+}
+
+/// Writes the summary and every frame's measurements as a JSON object. `percentiles` are over
+/// every pixel of every frame.
+pub fn write_json(
+    results: &[FrameInfo],
+    summary: &Summary,
+    percentiles: Option<&Percentiles>,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writeln!(writer, "{{")?;
+    writeln!(writer, "  \"summary\": {{")?;
+    writeln!(writer, "    \"frames\": {},", summary.frames)?;
+    writeln!(writer, "    \"max_cll\": {},", summary.max_cll)?;
+    writeln!(writer, "    \"max_cll_frame\": {},", summary.max_cll_frame)?;
+    writeln!(
+        writer,
+        "    \"robust_max_cll\": {},",
+        summary.robust_max_cll
+    )?;
+    writeln!(
+        writer,
+        "    \"robust_max_cll_frame\": {},",
+        summary.robust_max_cll_frame
+    )?;
+    writeln!(writer, "    \"max_fall\": {},", summary.max_fall)?;
+    writeln!(
+        writer,
+        "    \"max_fall_frame\": {},",
+        summary.max_fall_frame
+    )?;
+    writeln!(writer, "    \"avg_max\": {},", summary.avg_max)?;
+    writeln!(writer, "    \"avg_fall\": {},", summary.avg_fall)?;
+    writeln!(writer, "    \"max_min\": {},", summary.max_min)?;
+    writeln!(
+        writer,
+        "    \"percentiles_nits\": {}",
+        json_percentiles(percentiles)
+    )?;
+    writeln!(writer, "  }},")?;
+    writeln!(writer, "  \"frames\": [")?;
+
+    for (i, x) in results.iter().enumerate() {
+        let separator = if i + 1 < results.len() { "," } else { "" };
+        let timecode = x.time.map(|time| format!("\"{}\"", format_timestamp(time)));
+
+        writeln!(
+            writer,
+            "    {{\"frame\": {}, \"time\": {}, \"timecode\": {}, \
+             \"min_pq\": {}, \"avg_pq\": {}, \"max_pq\": {}, \"robust_max_pq\": {}, \
+             \"min_nits\": {}, \"avg_nits\": {}, \"max_nits\": {}, \"robust_max_nits\": {}, \
+             \"percentiles_nits\": {}}}{}",
+            x.frame,
+            json_or_null(x.time),
+            json_or_null(timecode),
+            x.min,
+            x.avg,
+            x.max,
+            x.robust_max,
+            pq_to_nits(x.min),
+            pq_to_nits(x.avg),
+            pq_to_nits(x.max),
+            pq_to_nits(x.robust_max),
+            json_percentiles(x.percentiles.as_ref()),
+            separator,
+        )?;
+    }
+
+    writeln!(writer, "  ]")?;
+    writeln!(writer, "}}")?;
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timecode() {
+        assert_eq!(format_timecode(0.0, 24.0), "00:00:00:00");
+        assert_eq!(format_timecode(1.5, 24.0), "00:00:01:12");
+        assert_eq!(format_timecode(3661.0, 25.0), "01:01:01:00");
+        assert_eq!(format_timecode(-1.0, 25.0), "00:00:00:00");
+    }
+
+    #[test]
+    fn timecode_at_fractional_rates() {
+        let fps = 24000.0 / 1001.0;
+
+        // Frames are counted at 24 fps, so timecode falls behind the clock
+        assert_eq!(format_timecode(1001.0 / 24000.0 * 23.0, fps), "00:00:00:23");
+        assert_eq!(format_timecode(1001.0 / 24000.0 * 24.0, fps), "00:00:01:00");
+        assert_eq!(format_timecode(60.0, fps), "00:00:59:23");
+    }
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(format_timestamp(0.0), "00:00:00.000");
+        assert_eq!(format_timestamp(3723.5), "01:02:03.500");
+        assert_eq!(format_timestamp(59.9996), "00:01:00.000");
+    }
+
+    #[test]
+    fn csv_columns() {
+        let results = [FrameInfo {
+            time: Some(1.5),
+            robust_max: 0.25,
+            percentiles: None,
+            ..FrameInfo::uniform(36, 0.5)
+        }];
+
+        let mut output = Vec::new();
+        write_csv(&results, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let mut lines = output.lines();
+        let header = lines.next().unwrap().split(',').collect::<Vec<_>>();
+        let row = lines.next().unwrap().split(',').collect::<Vec<_>>();
+        assert_eq!(lines.next(), None);
+        assert_eq!(header.len(), row.len());
+
+        let column = |name| row[header.iter().position(|&x| x == name).unwrap()];
+        assert_eq!(column("frame"), "36");
+        assert_eq!(column("timecode"), "00:00:01.500");
+        assert_eq!(column("max_pq"), "0.5");
+        assert_eq!(column("robust_max_pq"), "0.25");
+        assert_eq!(column("robust_max_nits"), pq_to_nits(0.25).to_string());
+        assert_eq!(column("p99_9_nits"), "");
+    }
+}
@@ -0,0 +1,520 @@
+use ffmpeg::color;
+use ffmpeg_next as ffmpeg;
+use rayon::prelude::*;
+use std::{fmt, iter};
+
+use crate::{
+    AnalyzerOptions,
+    hdr10plus::{DISTRIBUTION_INDEX, Distribution},
+    histogram::{Histogram, Percentiles, Profile},
+    pixel::{Components, FramePlanes},
+    pq::{PQ_10BIT_TO_NITS, nits_to_pq, yuv420_10bit_to_pq},
+};
+
+// Luma coefficients for BT.2020 non-constant-luminance YCbCr (ITU-R BT.2020, Table 4)
+pub const BT2020_KR: f64 = 0.2627;
+pub const BT2020_KB: f64 = 0.0593;
+pub const BT2020_KG: f64 = 1.0 - BT2020_KR - BT2020_KB;
+
+// Luma coefficients for BT.709 YCbCr (ITU-R BT.709, Item 3.2)
+pub const BT709_KR: f64 = 0.2126;
+pub const BT709_KB: f64 = 0.0722;
+
+// Luma coefficients for BT.601 YCbCr (ITU-R BT.601, Item 2.5.1)
+pub const BT601_KR: f64 = 0.299;
+pub const BT601_KB: f64 = 0.114;
+
+/// The quantisation range of a Y'CbCr or R'G'B' signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalRange {
+    /// Narrow ("TV", "MPEG") range: at 10 bits, Y' in 64..=940 and Cb/Cr in 64..=960.
+    Limited,
+    /// Full ("PC", "JPEG") range: at 10 bits, every component in 0..=1023.
+    Full,
+}
+
+impl SignalRange {
+    pub fn from_ffmpeg(range: color::Range) -> Option<Self> {
+        match range {
+            color::Range::MPEG => Some(SignalRange::Limited),
+            color::Range::JPEG => Some(SignalRange::Full),
+            color::Range::Unspecified => None,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "limited" | "tv" | "mpeg" => Some(SignalRange::Limited),
+            "full" | "pc" | "jpeg" => Some(SignalRange::Full),
+            _ => None,
+        }
+    }
+
+    /// Normalises a luma (or R', G', B') code value to the signal domain, so that reference
+    /// black is 0.0 and the nominal peak is 1.0.
+    fn luma_to_signal(self, sample: u16, bit_depth: u8) -> f64 {
+        let sample = sample as f64;
+        match self {
+            SignalRange::Limited => {
+                let scale = (1 << (bit_depth - 8)) as f64;
+                (sample - 16.0 * scale) / (219.0 * scale)
+            }
+            SignalRange::Full => sample / ((1 << bit_depth) - 1) as f64,
+        }
+    }
+
+    /// Normalises a chroma code value so that the nominal range is -0.5..=0.5.
+    fn chroma_to_signal(self, sample: u16, bit_depth: u8) -> f64 {
+        let sample = sample as f64;
+        match self {
+            SignalRange::Limited => {
+                let scale = (1 << (bit_depth - 8)) as f64;
+                (sample - 128.0 * scale) / (224.0 * scale)
+            }
+            SignalRange::Full => {
+                (sample - (1 << (bit_depth - 1)) as f64) / ((1 << bit_depth) - 1) as f64
+            }
+        }
+    }
+}
+
+/// The matrix coefficients a Y'CbCr signal was derived from R'G'B' with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matrix {
+    /// BT.2020 non-constant luminance, as HDR10 and HLG use.
+    Bt2020,
+    /// BT.709, as HD SDR uses.
+    Bt709,
+    /// BT.601, as SD SDR uses.
+    Bt601,
+}
+
+impl Matrix {
+    pub fn from_ffmpeg(space: color::Space) -> Option<Self> {
+        match space {
+            color::Space::BT2020NCL => Some(Matrix::Bt2020),
+            color::Space::BT709 => Some(Matrix::Bt709),
+            color::Space::BT470BG | color::Space::SMPTE170M => Some(Matrix::Bt601),
+            _ => None,
+        }
+    }
+
+    /// The luma coefficients of red and blue.
+    fn coefficients(self) -> (f64, f64) {
+        match self {
+            Matrix::Bt2020 => (BT2020_KR, BT2020_KB),
+            Matrix::Bt709 => (BT709_KR, BT709_KB),
+            Matrix::Bt601 => (BT601_KR, BT601_KB),
+        }
+    }
+
+    /// Converts a Y'CbCr triplet to R'G'B', all in the signal domain.
+    fn ycbcr_to_rgb(self, y: f64, cb: f64, cr: f64) -> [f64; 3] {
+        let (kr, kb) = self.coefficients();
+        let r = y + (2.0 - 2.0 * kr) * cr;
+        let b = y + (2.0 - 2.0 * kb) * cb;
+        let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+
+        [r, g, b].map(|x| x.clamp(0.0, 1.0))
+    }
+}
+
+/// An error for frames that were measured without something that is needed of them, which
+/// `AnalyzerOptions` has to ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotMeasured(pub &'static str);
+
+impl fmt::Display for NotMeasured {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "frames were not measured for {}", self.0)
+    }
+}
+
+impl std::error::Error for NotMeasured {}
+
+/// Per-frame measurements of display light, stored as PQ signal values whatever the transfer
+/// the frame was encoded with.
+#[derive(Debug)]
+pub struct FrameInfo {
+    /// Number of the frame in the stream, in presentation order from 0 at the start of the
+    /// stream, whether or not the frames before it were measured.
+    pub frame: usize,
+    /// Presentation time of the frame relative to the start of the stream, in seconds.
+    pub time: Option<f64>,
+    pub max: f64,
+    pub min: f64,
+    /// The PQ signal value of the frame's average light level. The average is taken in linear
+    /// light, so this is not the same as the average code value.
+    pub avg: f64,
+    /// The average PQ signal value, as Dolby Vision Level 1 metadata defines the average.
+    pub avg_signal: f64,
+    /// The highest R', G' and B' values, which `max` is the largest of.
+    pub max_channels: [f64; 3],
+    /// The maximum once the brightest few pixels are ignored, as
+    /// `AnalyzerOptions::ignore_brightest` asks.
+    pub robust_max: f64,
+    /// Max(R', G', B') at each of `PERCENTILES`, which unlike `max` a few hot pixels can't
+    /// dominate. Only measured if `AnalyzerOptions::percentiles` is set.
+    pub percentiles: Option<Percentiles>,
+    /// The coarse distribution that shot detection by histogram compares frames on. Only kept if
+    /// `AnalyzerOptions::profiles` is set.
+    pub profile: Option<Profile>,
+    /// The distribution HDR10+ metadata describes. Only measured if `AnalyzerOptions::hdr10plus`
+    /// is set.
+    pub distribution: Option<Distribution>,
+    /// The distribution of max(R', G', B') over the frame's pixels, if percentiles are measured.
+    /// `Analyzer::push` merges it into the distribution over every frame and drops it, as it is
+    /// far larger than the rest.
+    pub histogram: Option<Histogram>,
+}
+
+/// Running totals of the per-pixel measurements over part of a frame.
+#[derive(Debug, Clone)]
+struct Totals {
+    /// Sum of the linear light of every pixel, in nits.
+    sum: f64,
+    /// Sum of the 10-bit code values of every pixel.
+    sum_codes: u64,
+    max: u16,
+    min: u16,
+    max_channels: [u16; 3],
+    /// Only counted if something needs the distribution, as it is costly to allocate and merge.
+    histogram: Option<Histogram>,
+    count: usize,
+}
+
+impl Totals {
+    fn new(histogram: bool) -> Self {
+        Totals {
+            sum: 0.0,
+            sum_codes: 0,
+            max: 0,
+            min: u16::MAX,
+            max_channels: [0; 3],
+            histogram: histogram.then(Histogram::default),
+            count: 0,
+        }
+    }
+
+    /// Adds a pixel's R', G' and B' values, as 10-bit PQ code values.
+    fn push(&mut self, codes: [u16; 3]) {
+        let sample = codes[0].max(codes[1]).max(codes[2]);
+
+        self.sum += PQ_10BIT_TO_NITS[sample as usize];
+        self.sum_codes += sample as u64;
+        self.max = std::cmp::max(self.max, sample);
+        self.min = std::cmp::min(self.min, sample);
+        for (max, code) in self.max_channels.iter_mut().zip(codes) {
+            *max = std::cmp::max(*max, code);
+        }
+        if let Some(histogram) = &mut self.histogram {
+            histogram.add(sample);
+        }
+        self.count += 1;
+    }
+
+    fn merge(self, other: Self) -> Self {
+        let histogram = match (self.histogram, other.histogram) {
+            (Some(mut histogram), Some(other)) => {
+                histogram.merge(&other);
+                Some(histogram)
+            }
+            (histogram, other) => histogram.or(other),
+        };
+
+        Totals {
+            sum: self.sum + other.sum,
+            sum_codes: self.sum_codes + other.sum_codes,
+            max: std::cmp::max(self.max, other.max),
+            min: std::cmp::min(self.min, other.min),
+            max_channels: std::array::from_fn(|i| {
+                std::cmp::max(self.max_channels[i], other.max_channels[i])
+            }),
+            histogram,
+            count: self.count + other.count,
+        }
+    }
+}
+
+impl FrameInfo {
+    /// Measures a frame on the per-pixel max(R', G', B'), as CTA-861.3 defines MaxCLL.
+    ///
+    /// Subsampled chroma is upsampled by nearest neighbour. Rows are measured in parallel.
+    pub fn measure(
+        frame: usize,
+        time: Option<f64>,
+        planes: &FramePlanes,
+        range: SignalRange,
+        options: &AnalyzerOptions,
+    ) -> Self {
+        // Enough work per task to outweigh rayon's scheduling overhead
+        const MIN_ROWS_PER_TASK: usize = 16;
+
+        let height = planes.height();
+
+        let matrix = options.matrix.unwrap_or(options.transfer.default_matrix());
+        let to_pq = options.transfer.to_10bit_pq();
+
+        let needs_histogram = options.percentiles
+            || options.profiles
+            || options.hdr10plus
+            || options.ignore_brightest > 0;
+        let new_totals = || Totals::new(needs_histogram);
+
+        let totals = (0..height)
+            .into_par_iter()
+            .with_min_len(MIN_ROWS_PER_TASK)
+            .fold(new_totals, |mut totals, row| {
+                Self::measure_row(planes, range, matrix, &to_pq, row, &mut totals);
+                totals
+            })
+            .reduce(new_totals, Totals::merge);
+
+        let avg = totals.sum / totals.count as f64;
+        let avg_code = totals.sum_codes as f64 / totals.count as f64;
+        let histogram = totals.histogram;
+        let robust_max = histogram
+            .as_ref()
+            .map_or(totals.max, |x| x.max_excluding(options.ignore_brightest));
+        let distribution = |histogram: &Histogram| {
+            DISTRIBUTION_INDEX
+                .map(|percent| yuv420_10bit_to_pq(histogram.percentile(percent as f64)))
+        };
+
+        FrameInfo {
+            frame,
+            time,
+            max: yuv420_10bit_to_pq(totals.max),
+            min: yuv420_10bit_to_pq(totals.min),
+            avg: nits_to_pq(avg),
+            avg_signal: avg_code / 1023.0,
+            max_channels: totals.max_channels.map(yuv420_10bit_to_pq),
+            robust_max: yuv420_10bit_to_pq(robust_max),
+            percentiles: histogram
+                .as_ref()
+                .filter(|_| options.percentiles)
+                .map(Histogram::percentiles),
+            profile: histogram
+                .as_ref()
+                .filter(|_| options.profiles)
+                .map(Histogram::profile),
+            distribution: histogram
+                .as_ref()
+                .filter(|_| options.hdr10plus)
+                .map(distribution),
+            histogram: histogram.filter(|_| options.percentiles),
+        }
+    }
+
+    fn measure_row(
+        planes: &FramePlanes,
+        range: SignalRange,
+        matrix: Matrix,
+        to_pq: &impl Fn([f64; 3]) -> [u16; 3],
+        row: usize,
+        totals: &mut Totals,
+    ) {
+        let bit_depth = planes.bit_depth;
+
+        match planes.components {
+            Components::YCbCr {
+                ref y,
+                ref cb,
+                ref cr,
+                chroma_shift: (shift_w, shift_h),
+            } => {
+                // Each chroma row covers 2^shift_h luma rows, and each chroma sample covers
+                // 2^shift_w luma samples
+                let chroma_row = row >> shift_h;
+                let chroma = cb
+                    .row(chroma_row)
+                    .zip(cr.row(chroma_row))
+                    .flat_map(|cbcr| iter::repeat_n(cbcr, 1 << shift_w));
+
+                for (y, (cb, cr)) in y.row(row).zip(chroma) {
+                    totals.push(to_pq(matrix.ycbcr_to_rgb(
+                        range.luma_to_signal(y, bit_depth),
+                        range.chroma_to_signal(cb, bit_depth),
+                        range.chroma_to_signal(cr, bit_depth),
+                    )));
+                }
+            }
+            Components::Gbr {
+                ref g,
+                ref b,
+                ref r,
+            } => {
+                for ((g, b), r) in g.row(row).zip(b.row(row)).zip(r.row(row)) {
+                    let rgb = [r, g, b].map(|x| range.luma_to_signal(x, bit_depth));
+                    totals.push(to_pq(rgb.map(|x| x.clamp(0.0, 1.0))));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl FrameInfo {
+    /// A frame with every pixel at the same PQ signal value, for testing what uses measurements.
+    pub(crate) fn uniform(frame: usize, pq: f64) -> Self {
+        FrameInfo {
+            frame,
+            time: None,
+            max: pq,
+            min: pq,
+            avg: pq,
+            avg_signal: pq,
+            max_channels: [pq; 3],
+            robust_max: pq,
+            percentiles: Some([pq; crate::histogram::PERCENTILES.len()]),
+            profile: None,
+            distribution: None,
+            histogram: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    /// Converts R'G'B' to Y'CbCr the way an encoder would.
+    fn rgb_to_ycbcr(matrix: Matrix, [r, g, b]: [f64; 3]) -> (f64, f64, f64) {
+        let (kr, kb) = matrix.coefficients();
+        let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+        (y, (b - y) / (2.0 - 2.0 * kb), (r - y) / (2.0 - 2.0 * kr))
+    }
+
+    #[test]
+    fn limited_range() {
+        let range = SignalRange::Limited;
+
+        assert_close(range.luma_to_signal(64, 10), 0.0);
+        assert_close(range.luma_to_signal(940, 10), 1.0);
+        assert_close(range.luma_to_signal(502, 10), 0.5);
+        assert_close(range.luma_to_signal(16, 8), 0.0);
+        assert_close(range.luma_to_signal(235, 8), 1.0);
+        assert_close(range.luma_to_signal(256, 12), 0.0);
+        assert_close(range.luma_to_signal(3760, 12), 1.0);
+
+        assert_close(range.chroma_to_signal(64, 10), -0.5);
+        assert_close(range.chroma_to_signal(512, 10), 0.0);
+        assert_close(range.chroma_to_signal(960, 10), 0.5);
+        assert_close(range.chroma_to_signal(16, 8), -0.5);
+        assert_close(range.chroma_to_signal(240, 8), 0.5);
+    }
+
+    #[test]
+    fn limited_range_excursions() {
+        // Footroom and headroom fall outside 0.0..=1.0, for the conversion to clamp
+        assert!(SignalRange::Limited.luma_to_signal(4, 10) < 0.0);
+        assert!(SignalRange::Limited.luma_to_signal(1019, 10) > 1.0);
+    }
+
+    #[test]
+    fn full_range() {
+        let range = SignalRange::Full;
+
+        assert_close(range.luma_to_signal(0, 10), 0.0);
+        assert_close(range.luma_to_signal(1023, 10), 1.0);
+        assert_close(range.luma_to_signal(255, 8), 1.0);
+
+        assert_close(range.chroma_to_signal(512, 10), 0.0);
+        assert_close(range.chroma_to_signal(128, 8), 0.0);
+        assert_close(range.chroma_to_signal(1023, 10), 511.0 / 1023.0);
+    }
+
+    #[test]
+    fn parse_range() {
+        assert_eq!(SignalRange::parse("tv"), Some(SignalRange::Limited));
+        assert_eq!(SignalRange::parse("limited"), Some(SignalRange::Limited));
+        assert_eq!(SignalRange::parse("pc"), Some(SignalRange::Full));
+        assert_eq!(SignalRange::parse("jpeg"), Some(SignalRange::Full));
+        assert_eq!(SignalRange::parse("auto"), None);
+    }
+
+    #[test]
+    fn bt2020_coefficients() {
+        assert_close(BT2020_KG, 0.6780);
+    }
+
+    #[test]
+    fn bt2020_primaries() {
+        // BT.2020 Table 4: E'Y = 0.2627 E'R + 0.6780 E'G + 0.0593 E'B
+        let (y, cb, cr) = rgb_to_ycbcr(Matrix::Bt2020, [1.0, 0.0, 0.0]);
+        assert_close(y, 0.2627);
+        assert_close(cr, 0.5);
+        assert_eq!(Matrix::Bt2020.ycbcr_to_rgb(y, cb, cr), [1.0, 0.0, 0.0]);
+
+        let (y, cb, cr) = rgb_to_ycbcr(Matrix::Bt2020, [0.0, 0.0, 1.0]);
+        assert_close(y, 0.0593);
+        assert_close(cb, 0.5);
+        let [r, g, b] = Matrix::Bt2020.ycbcr_to_rgb(y, cb, cr);
+        assert_close(r, 0.0);
+        assert_close(g, 0.0);
+        assert_close(b, 1.0);
+    }
+
+    #[test]
+    fn ycbcr_round_trip() {
+        let colours = [
+            [0.5, 0.5, 0.5],
+            [0.9, 0.4, 0.1],
+            [0.2, 0.7, 0.3],
+            [0.05, 0.1, 0.8],
+        ];
+
+        for matrix in [Matrix::Bt2020, Matrix::Bt709, Matrix::Bt601] {
+            for rgb in colours {
+                let (y, cb, cr) = rgb_to_ycbcr(matrix, rgb);
+                let converted = matrix.ycbcr_to_rgb(y, cb, cr);
+                for (actual, expected) in converted.into_iter().zip(rgb) {
+                    assert_close(actual, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grey_has_no_chroma() {
+        for matrix in [Matrix::Bt2020, Matrix::Bt709, Matrix::Bt601] {
+            assert_eq!(matrix.ycbcr_to_rgb(0.25, 0.0, 0.0), [0.25; 3]);
+        }
+    }
+
+    #[test]
+    fn average_in_linear_light() {
+        let samples: [u16; 2] = [0, 1023];
+        let plane = || crate::pixel::Plane::new(bytemuck::cast_slice(&samples), 4, 2, 1, 10);
+        let planes =
+            FramePlanes::gbr(plane().unwrap(), plane().unwrap(), plane().unwrap(), 10).unwrap();
+
+        let options = AnalyzerOptions::default();
+        let frame = FrameInfo::measure(0, None, &planes, SignalRange::Full, &options);
+
+        // Black and 10000 nits average to 5000 nits, far above the middle PQ code value
+        assert_close(frame.avg, nits_to_pq(5000.0));
+        assert!(frame.avg > 0.9);
+        assert_close(frame.avg_signal, 0.5);
+        assert_eq!((frame.min, frame.max), (0.0, 1.0));
+    }
+
+    #[test]
+    fn out_of_gamut_is_clamped() {
+        // A magenta more saturated than R'G'B' can hold, with negative G' and B' above 1.0
+        let [r, g, b] = Matrix::Bt2020.ycbcr_to_rgb(0.2, 0.5, 0.5);
+        assert_close(r, 0.2 + (1.0 - BT2020_KR));
+        assert_eq!([g, b], [0.0, 1.0]);
+
+        let [r, g, b] = Matrix::Bt2020.ycbcr_to_rgb(0.0, -0.5, -0.5);
+        assert_eq!([r, b], [0.0, 0.0]);
+        assert!((0.0..=1.0).contains(&g));
+    }
+}
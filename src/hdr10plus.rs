@@ -0,0 +1,223 @@
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::{FrameInfo, frame::NotMeasured, pq_to_nits, scene::shot_frames};
+
+/// The maxRGB percentiles each scene's distribution is described by, as most HDR10+ content
+/// uses.
+pub const DISTRIBUTION_INDEX: [u8; 9] = [1, 5, 10, 25, 50, 75, 90, 95, 99];
+
+/// Max(R', G', B') at each of `DISTRIBUTION_INDEX`, as PQ signal values.
+pub type Distribution = [f64; DISTRIBUTION_INDEX.len()];
+
+/// Converts nits to the units ST 2094-40 uses for luminances: 0.00001 of the PQ peak of
+/// 10000 nits, so 0.1 nits.
+fn nits_to_units(nits: f64) -> u32 {
+    (nits * 10.0).round().clamp(0.0, 100000.0) as u32
+}
+
+/// The luminance parameters of one scene.
+struct SceneParameters {
+    max_scl: [u32; 3],
+    average_max_rgb: u32,
+    distribution: [u32; DISTRIBUTION_INDEX.len()],
+}
+
+impl SceneParameters {
+    /// Describes a scene by its frames. Each percentile of the scene is taken to be the average
+    /// of the frames' in nits, as only those are kept.
+    fn new(frames: &[FrameInfo]) -> Result<Self, NotMeasured> {
+        let distributions = frames
+            .iter()
+            .map(|x| x.distribution.ok_or(NotMeasured("HDR10+ metadata")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let max_scl = std::array::from_fn(|i| {
+            let max = frames.iter().map(|x| x.max_channels[i]).fold(0.0, f64::max);
+            nits_to_units(pq_to_nits(max))
+        });
+        let average = frames.iter().map(|x| pq_to_nits(x.avg)).sum::<f64>() / frames.len() as f64;
+        let distribution = std::array::from_fn(|i| {
+            let sum: f64 = distributions.iter().map(|x| pq_to_nits(x[i])).sum();
+            nits_to_units(sum / frames.len() as f64)
+        });
+
+        Ok(SceneParameters {
+            max_scl,
+            average_max_rgb: nits_to_units(average),
+            distribution,
+        })
+    }
+}
+
+/// Joins values into the body of a JSON array.
+fn join(values: impl IntoIterator<Item = impl ToString>) -> String {
+    values
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Writes HDR10+ (SMPTE ST 2094-40) metadata in the JSON format hdr10plus_tool reads, with
+/// the luminance parameters of each shot repeated for every frame in it.
+///
+/// The metadata is profile A, without a tone mapping curve, for a single window covering the
+/// whole frame. Nothing is written unless every frame was measured for HDR10+, as
+/// `AnalyzerOptions::hdr10plus` asks, and every shot is a non-empty range of `results`.
+pub fn write_json(
+    results: &[FrameInfo],
+    shots: &[Range<usize>],
+    mut writer: impl Write,
+) -> io::Result<()> {
+    let scenes = shots
+        .iter()
+        .map(|shot| {
+            SceneParameters::new(shot_frames(results, shot)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    writeln!(writer, "{{")?;
+    writeln!(
+        writer,
+        "  \"JSONInfo\": {{\"HDR10plusProfile\": \"A\", \"Version\": \"1.0\"}},"
+    )?;
+    writeln!(writer, "  \"SceneInfo\": [")?;
+
+    for (scene, (shot, parameters)) in shots.iter().zip(&scenes).enumerate() {
+        for frame in shot.clone() {
+            let last = scene + 1 == shots.len() && frame + 1 == shot.end;
+            let separator = if last { "" } else { "," };
+
+            writeln!(writer, "    {{")?;
+            writeln!(writer, "      \"LuminanceParameters\": {{")?;
+            writeln!(
+                writer,
+                "        \"AverageRGB\": {},",
+                parameters.average_max_rgb
+            )?;
+            writeln!(writer, "        \"LuminanceDistributions\": {{")?;
+            writeln!(
+                writer,
+                "          \"DistributionIndex\": [{}],",
+                join(DISTRIBUTION_INDEX)
+            )?;
+            writeln!(
+                writer,
+                "          \"DistributionValues\": [{}]",
+                join(parameters.distribution)
+            )?;
+            writeln!(writer, "        }},")?;
+            writeln!(writer, "        \"MaxScl\": [{}]", join(parameters.max_scl))?;
+            writeln!(writer, "      }},")?;
+            writeln!(writer, "      \"NumberOfWindows\": 1,")?;
+            writeln!(
+                writer,
+                "      \"TargetedSystemDisplayMaximumLuminance\": 0,"
+            )?;
+            writeln!(writer, "      \"SceneFrameIndex\": {},", frame - shot.start)?;
+            writeln!(writer, "      \"SceneId\": {scene},")?;
+            writeln!(writer, "      \"SequenceFrameIndex\": {frame}")?;
+            writeln!(writer, "    }}{separator}")?;
+        }
+    }
+
+    writeln!(writer, "  ],")?;
+    writeln!(writer, "  \"SceneInfoSummary\": {{")?;
+    writeln!(
+        writer,
+        "    \"SceneFirstFrameIndex\": [{}],",
+        join(shots.iter().map(|x| x.start))
+    )?;
+    writeln!(
+        writer,
+        "    \"SceneFrameNumbers\": [{}]",
+        join(shots.iter().map(|x| x.len()))
+    )?;
+    writeln!(writer, "  }},")?;
+    writeln!(
+        writer,
+        "  \"ToolInfo\": {{\"Tool\": \"measure-hdr\", \"Version\": \"{}\"}}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "}}")?;
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(frame: usize, pq: f64) -> FrameInfo {
+        FrameInfo {
+            distribution: Some([pq; DISTRIBUTION_INDEX.len()]),
+            ..FrameInfo::uniform(frame, pq)
+        }
+    }
+
+    #[test]
+    fn scene_parameters() {
+        let frames = [measured(0, 1.0), measured(1, 0.0)];
+        let parameters = SceneParameters::new(&frames).unwrap();
+
+        assert_eq!(parameters.max_scl, [100000; 3]);
+        assert_eq!(parameters.average_max_rgb, 50000);
+        assert_eq!(parameters.distribution, [50000; DISTRIBUTION_INDEX.len()]);
+    }
+
+    #[test]
+    fn frames_per_scene() {
+        let frames = [measured(0, 1.0), measured(1, 0.0), measured(2, 0.0)];
+        let mut json = Vec::new();
+        write_json(&frames, &[0..1, 1..3], &mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert_eq!(json.matches("\"LuminanceParameters\"").count(), 3);
+        assert!(json.contains("\"SceneId\": 1,\n      \"SequenceFrameIndex\": 2\n    }\n  ],"));
+        assert!(json.contains("\"SceneFirstFrameIndex\": [0, 1],"));
+        assert!(json.contains("\"SceneFrameNumbers\": [1, 2]"));
+    }
+
+    #[test]
+    fn shots_not_covering_every_frame() {
+        let frames = [0.0, 1.0, 0.0, 0.0].map(|pq| measured(0, pq));
+        let mut json = Vec::new();
+        write_json(&frames, &[1..2, 2..3], &mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert_eq!(json.matches("\"LuminanceParameters\"").count(), 2);
+        assert_eq!(json.matches("\n    },\n").count(), 1);
+        assert!(json.contains("\"SequenceFrameIndex\": 2\n    }\n  ],"));
+    }
+
+    #[test]
+    fn invalid_shots() {
+        let frames = [measured(0, 1.0), measured(1, 0.0)];
+
+        for shots in [
+            vec![0..1, 1..1],
+            vec![0..1, 1..3],
+            vec![0..1, Range { start: 2, end: 1 }],
+        ] {
+            let mut json = Vec::new();
+            let error = write_json(&frames, &shots, &mut json).unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{shots:?}");
+            assert!(json.is_empty());
+        }
+    }
+
+    #[test]
+    fn unmeasured_frames() {
+        let frames = [measured(0, 1.0), FrameInfo::uniform(1, 0.0)];
+        let mut json = Vec::new();
+        let error = write_json(&frames, &[0..1, 1..2], &mut json).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(json.is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::pq::yuv420_10bit_to_pq;
+
+/// The percentiles of max(R', G', B') measured for every frame.
+pub const PERCENTILES: [f64; 4] = [50.0, 99.0, 99.9, 99.99];
+
+/// The PQ signal values at each of `PERCENTILES`.
+pub type Percentiles = [f64; PERCENTILES.len()];
+
+/// A short name for one of `PERCENTILES`, such as "P99.9".
+pub fn percentile_name(percent: f64) -> String {
+    if percent == 50.0 {
+        "Median".to_owned()
+    } else {
+        format!("P{percent}")
+    }
+}
+
+/// Number of pixels at each 10-bit PQ code value.
+#[derive(Debug, Clone)]
+pub struct Histogram(Box<[u32; 1024]>);
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram(Box::new([0; 1024]))
+    }
+}
+
+impl Histogram {
+    pub fn add(&mut self, code: u16) {
+        self.0[code as usize] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (count, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *count += other;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.iter().map(|&x| x as u64).sum()
+    }
+
+    /// The highest code value left once the `n` brightest pixels are set aside.
+    pub fn max_excluding(&self, n: u64) -> u16 {
+        let mut skipped = 0;
+
+        for (code, &count) in self.0.iter().enumerate().rev() {
+            skipped += count as u64;
+            if skipped > n {
+                return code as u16;
+            }
+        }
+
+        0
+    }
+
+    /// The PQ signal value at each of `PERCENTILES`.
+    pub fn percentiles(&self) -> Percentiles {
+        PERCENTILES.map(|percent| yuv420_10bit_to_pq(self.percentile(percent)))
+    }
+
+    /// The distribution at the much coarser resolution of a `Profile`.
+    pub fn profile(&self) -> Profile {
+        let count = self.count().max(1) as f64;
+
+        Profile(std::array::from_fn(|bin| {
+            let codes = bin * PROFILE_BIN_WIDTH..(bin + 1) * PROFILE_BIN_WIDTH;
+            let pixels: u64 = self.0[codes].iter().map(|&x| x as u64).sum();
+            (pixels as f64 / count) as f32
+        }))
+    }
+
+    /// The lowest code value that at least `percent`% of pixels are at or below.
+    pub fn percentile(&self, percent: f64) -> u16 {
+        let target = (self.count() as f64 * percent / 100.0).ceil() as u64;
+        let mut seen = 0;
+
+        for (code, &count) in self.0.iter().enumerate() {
+            seen += count as u64;
+            if seen >= target.max(1) {
+                return code as u16;
+            }
+        }
+
+        // Only reached if the histogram is empty
+        0
+    }
+}
+
+/// Number of 10-bit PQ code values each bin of a `Profile` covers.
+const PROFILE_BIN_WIDTH: usize = 32;
+
+/// The shape of a distribution at a much coarser resolution than `Histogram`, as the fraction of
+/// pixels in each of 32 equal ranges of code values. Small enough to keep for every frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile([f32; 1024 / PROFILE_BIN_WIDTH]);
+
+impl Profile {
+    /// How different two distributions are, from 0.0 for identical ones to 1.0 for ones with no
+    /// bins in common.
+    pub fn difference(&self, other: &Self) -> f64 {
+        let distance: f64 = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(&a, &b)| (a as f64 - b as f64).abs())
+            .sum();
+
+        distance / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(counts: &[(u16, u32)]) -> Histogram {
+        let mut histogram = Histogram::default();
+        for &(code, count) in counts {
+            for _ in 0..count {
+                histogram.add(code);
+            }
+        }
+        histogram
+    }
+
+    #[test]
+    fn percentile() {
+        // 100 pixels: 90 at 100, 9 at 500 and 1 at 900
+        let histogram = histogram(&[(100, 90), (500, 9), (900, 1)]);
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.percentile(0.0), 100);
+        assert_eq!(histogram.percentile(50.0), 100);
+        assert_eq!(histogram.percentile(90.0), 100);
+        assert_eq!(histogram.percentile(90.5), 500);
+        assert_eq!(histogram.percentile(99.0), 500);
+        assert_eq!(histogram.percentile(99.9), 900);
+        assert_eq!(histogram.percentile(100.0), 900);
+    }
+
+    #[test]
+    fn empty_percentile() {
+        assert_eq!(Histogram::default().percentile(50.0), 0);
+        assert_eq!(Histogram::default().percentiles(), [0.0; PERCENTILES.len()]);
+    }
+
+    #[test]
+    fn max_excluding() {
+        let histogram = histogram(&[(100, 90), (500, 9), (900, 1)]);
+
+        assert_eq!(histogram.max_excluding(0), 900);
+        assert_eq!(histogram.max_excluding(1), 500);
+        assert_eq!(histogram.max_excluding(9), 500);
+        assert_eq!(histogram.max_excluding(10), 100);
+        assert_eq!(histogram.max_excluding(99), 100);
+        assert_eq!(histogram.max_excluding(100), 0);
+    }
+
+    #[test]
+    fn merge() {
+        let mut merged = histogram(&[(100, 2)]);
+        merged.merge(&histogram(&[(100, 1), (200, 3)]));
+
+        assert_eq!(merged.count(), 6);
+        assert_eq!(merged.percentile(50.0), 100);
+        assert_eq!(merged.percentile(51.0), 200);
+    }
+
+    #[test]
+    fn profile_difference() {
+        let dark = histogram(&[(10, 100)]).profile();
+        let bright = histogram(&[(900, 100)]).profile();
+        // Half the pixels move from the first bin to the last
+        let mixed = histogram(&[(10, 50), (900, 50)]).profile();
+        // Within the same bins as `dark`, so no different at this resolution
+        let also_dark = histogram(&[(0, 30), (31, 70)]).profile();
+
+        assert_eq!(dark.difference(&dark), 0.0);
+        assert_eq!(dark.difference(&bright), 1.0);
+        assert_eq!(dark.difference(&mixed), 0.5);
+        assert_eq!(mixed.difference(&dark), 0.5);
+        assert_eq!(dark.difference(&also_dark), 0.0);
+    }
+
+    #[test]
+    fn profile_ignores_pixel_count() {
+        let small = histogram(&[(100, 1), (600, 3)]).profile();
+        let large = histogram(&[(100, 100), (600, 300)]).profile();
+
+        assert_eq!(small.difference(&large), 0.0);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::frame::{BT2020_KB, BT2020_KG, BT2020_KR};
+
+// Constants from the ARIB STD-B67 HLG OETF (ITU-R BT.2100, Table 5)
+pub const HLG_A: f64 = 0.178_832_77;
+pub const HLG_B: f64 = 1.0 - 4.0 * HLG_A;
+pub const HLG_C: f64 = 0.559_910_73;
+
+/// The nominal peak luminance of the reference HLG display, in nits.
+pub const HLG_NOMINAL_PEAK: f64 = 1000.0;
+
+/// Converts an HLG signal value to normalised scene light, in 0.0..=1.0.
+pub fn hlg_inverse_oetf(signal: f64) -> f64 {
+    let signal = signal.clamp(0.0, 1.0);
+
+    if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+    }
+}
+
+/// The OOTF system gamma for a display with the given nominal peak, in nits
+/// (ITU-R BT.2100, Table 5, note 5e).
+pub fn hlg_system_gamma(nominal_peak: f64) -> f64 {
+    1.2 + 0.42 * (nominal_peak / HLG_NOMINAL_PEAK).log10()
+}
+
+/// Converts HLG R', G' and B' signal values to the display light of each, in nits, on a display
+/// with the given nominal peak and a black level of zero.
+pub fn hlg_to_nits(rgb: [f64; 3], nominal_peak: f64, system_gamma: f64) -> [f64; 3] {
+    let [r, g, b] = rgb.map(hlg_inverse_oetf);
+    let luminance = BT2020_KR * r + BT2020_KG * g + BT2020_KB * b;
+
+    // The OOTF scales all three components by the same amount, so that hues are preserved
+    let scale = if luminance > 0.0 {
+        nominal_peak * luminance.powf(system_gamma - 1.0)
+    } else {
+        0.0
+    };
+
+    [r, g, b].map(|x| x * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    #[test]
+    fn inverse_oetf() {
+        assert_eq!(hlg_inverse_oetf(0.0), 0.0);
+        assert_close(hlg_inverse_oetf(0.25), 1.0 / 48.0, 1e-12);
+        // The two segments meet at a signal of 0.5
+        assert_close(hlg_inverse_oetf(0.5), 1.0 / 12.0, 1e-7);
+        assert_close(hlg_inverse_oetf(0.5 + 1e-9), 1.0 / 12.0, 1e-7);
+        assert_close(hlg_inverse_oetf(1.0), 1.0, 1e-6);
+        assert_eq!(hlg_inverse_oetf(1.5), hlg_inverse_oetf(1.0));
+    }
+
+    #[test]
+    fn system_gamma() {
+        assert_close(hlg_system_gamma(1000.0), 1.2, 1e-12);
+        assert_close(hlg_system_gamma(2000.0), 1.326, 1e-3);
+        assert_close(hlg_system_gamma(400.0), 1.033, 1e-3);
+    }
+
+    #[test]
+    fn reference_white() {
+        // ITU-R BT.2408: HDR reference white, a 75% HLG signal, is shown at 203 nits on the
+        // 1000 nit reference display
+        let [r, g, b] = hlg_to_nits([0.75; 3], 1000.0, 1.2);
+        assert_close(r, 203.0, 0.5);
+        assert_eq!((r, r), (g, b));
+
+        let [peak, ..] = hlg_to_nits([1.0; 3], 1000.0, 1.2);
+        assert_close(peak, 1000.0, 1e-3);
+        assert_eq!(hlg_to_nits([0.0; 3], 1000.0, 1.2), [0.0; 3]);
+    }
+
+    #[test]
+    fn ootf_preserves_hue() {
+        let scene = [0.9, 0.6, 0.3].map(hlg_inverse_oetf);
+        let display = hlg_to_nits([0.9, 0.6, 0.3], 1000.0, 1.2);
+
+        assert_close(display[1] / display[0], scene[1] / scene[0], 1e-12);
+        assert_close(display[2] / display[0], scene[2] / scene[0], 1e-12);
+    }
+}
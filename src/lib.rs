@@ -0,0 +1,32 @@
+//! Measures the light levels of PQ, HLG and SDR video: the per-frame maximum, average and
+//! minimum, and the MaxCLL and MaxFALL of a whole stream.
+//!
+//! Frames are fed to an [`Analyzer`], as decoded FFmpeg frames or as planes of samples, which
+//! hands back a [`FrameInfo`] for each and a [`Summary`] at the end. Measurements are stored as
+//! PQ signal values, which [`pq_to_nits`] converts to nits.
+
+pub use ffmpeg_next as ffmpeg;
+
+pub mod analyzer;
+pub mod bt1886;
+pub mod compare;
+pub mod dovi;
+pub mod encoder;
+pub mod export;
+pub mod frame;
+pub mod hdr10plus;
+pub mod histogram;
+pub mod hlg;
+pub mod metadata;
+pub mod pdf;
+pub mod pixel;
+pub mod plot;
+pub mod pq;
+pub mod report;
+pub mod scene;
+pub mod transfer;
+
+pub use analyzer::{Analysis, Analyzer, AnalyzerOptions, Summary};
+pub use frame::{FrameInfo, Matrix, SignalRange};
+pub use pq::{nits_to_pq, pq_to_nits};
+pub use transfer::Transfer;
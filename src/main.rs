@@ -1,330 +1,758 @@
-use ffmpeg::{format, media, util::frame::video::Video};
-use ffmpeg_next as ffmpeg;
-use float_ord::FloatOrd;
-use plotters::{
-    coord::ranged1d::{KeyPointHint, NoDefaultFormatting, ValueFormatter},
-    prelude::*,
+use cli::{Args, Command, Input, Position, Verbosity};
+use measure_hdr::{
+    Analysis, Analyzer, AnalyzerOptions, FrameInfo, Matrix, SignalRange, Summary, Transfer,
+    compare::{self, Comparison, Level},
+    dovi,
+    encoder::EncoderParams,
+    export,
+    ffmpeg::{
+        self, Discard, color::TransferCharacteristic, format, media, threading,
+        util::frame::video::Video,
+    },
+    hdr10plus,
+    histogram::{PERCENTILES, Percentiles, percentile_name},
+    metadata::{ContentLightLevel, StaticMetadata},
+    pixel::{FormatError, FrameReader},
+    plot, pq_to_nits, report, scene,
+};
+use rayon::prelude::*;
+use std::{
+    env, error::Error, fs::File, io::BufWriter, iter, ops::ControlFlow, path::Path,
+    process::ExitCode, sync::mpsc, thread, time::Instant,
 };
-use std::{env, ops::Range, time::Instant};
 
-// Contants from the SMPTE 2084 PQ spec
-pub const ST2084_Y_MAX: f64 = 10000.0;
-pub const ST2084_M1: f64 = 2610.0 / 16384.0;
-pub const ST2084_M2: f64 = (2523.0 / 4096.0) * 128.0;
-pub const ST2084_C1: f64 = 3424.0 / 4096.0;
-pub const ST2084_C2: f64 = (2413.0 / 4096.0) * 32.0;
-pub const ST2084_C3: f64 = (2392.0 / 4096.0) * 32.0;
+mod cli;
 
-const MAX_COLOUR: RGBColor = RGBColor(65, 105, 225);
-const AVERAGE_COLOUR: RGBColor = RGBColor(75, 0, 130);
-const MIN_COLOUR: RGBColor = BLACK;
+fn main() -> ExitCode {
+    let args = match Command::parse(env::args().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            print!("{}", cli::HELP);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("measure-hdr {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\nFor more information, try '--help'.");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(code) => code,
+        // Kept apart from a failed comparison's status, so scripts can tell the two apart
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
 
-fn pq_to_nits(pq: f64) -> f64 {
-    let pq = pq.clamp(0.0, 1.0);
+/// Measures and reports on the inputs, returning a failing status if a comparison failed.
+fn run(args: &Args) -> Result<ExitCode, Box<dyn Error>> {
+    ffmpeg::init()?;
 
-    // Inverse EOTF for PQ
-    let v_p = pq.powf(1.0 / ST2084_M2);
-    let n = ((v_p - ST2084_C1).max(0.0) / (ST2084_C2 - ST2084_C3 * v_p)).powf(1.0 / ST2084_M1);
+    let normal = args.verbosity >= Verbosity::Normal;
 
-    n * ST2084_Y_MAX
-}
+    let mut measurements = Vec::new();
+    for input in &args.inputs {
+        if normal && args.inputs.len() > 1 {
+            println!("== {} ==", input.label);
+        }
+        measurements.push(measure(&input.path, args)?);
+    }
 
-fn yuv420_10bit_to_pq(sample: u16) -> f64 {
-    const YUV420_10BIT_MAX: f64 = 1023.0;
-    let pq_code_value = (sample as f64).clamp(0.0, YUV420_10BIT_MAX);
+    // Only the plot can show several inputs, so everything else is of the first
+    let Measurement {
+        results,
+        percentiles,
+        summary,
+        metadata,
+        transfer,
+        properties,
+        fps,
+    } = &measurements[0];
+
+    let title = args.title.clone().unwrap_or_else(|| {
+        match transfer {
+            Transfer::Pq => "SMPTE 2084 PQ Measurements Plot",
+            Transfer::Hlg { .. } => "ARIB STD-B67 HLG Measurements Plot",
+            Transfer::Sdr { .. } => "BT.1886 SDR Measurements Plot",
+        }
+        .to_owned()
+    });
+
+    if args.encoder_params {
+        let params = EncoderParams {
+            content_light_level: ContentLightLevel::from_summary(summary),
+            mastering_display: metadata.mastering_display,
+        };
+
+        println!("x265: {}", params.x265());
+        println!("SVT-AV1: {}", params.svt_av1());
+        println!("mkvmerge (track 0): {}", params.mkvmerge(0));
+    }
 
-    pq_code_value / 1023.0
-}
+    let passed = match &measurements[..] {
+        [reference, other] if args.compare => {
+            let (reference_input, other_input) = (&args.inputs[0], &args.inputs[1]);
+            let comparison = Comparison::new(
+                &reference.results,
+                &other.results,
+                other_input.offset - reference_input.offset,
+            );
+            report_comparison(&comparison, reference_input, other_input, args)?
+        }
+        _ => true,
+    };
 
-pub fn nits_to_pq(nits: f64) -> f64 {
-    let y = nits / ST2084_Y_MAX;
+    if let Some(path) = &args.csv
+        && !args.compare
+    {
+        export::write_csv(results, BufWriter::new(File::create(path)?))?;
+    }
+    if let Some(path) = &args.json {
+        let writer = BufWriter::new(File::create(path)?);
+        export::write_json(results, summary, percentiles.as_ref(), writer)?;
+    }
 
-    ((ST2084_C1 + ST2084_C2 * y.powf(ST2084_M1)) / (1.0 + ST2084_C3 * y.powf(ST2084_M1)))
-        .powf(ST2084_M2)
-}
+    let shots = if args.needs_shots() {
+        scene::detect_shots(results, args.scene_detector)?
+    } else {
+        Vec::new()
+    };
+
+    if normal && !shots.is_empty() {
+        println!("Detected {} shots", shots.len());
+    }
+
+    if args.shots {
+        for (i, frames) in shots.iter().enumerate() {
+            let shot = scene::Shot::new(results, frames.clone(), *fps)
+                .expect("detected shots are never empty");
+            let (first, last) = (shot.stream_frames.start, shot.stream_frames.end - 1);
+
+            println!(
+                "Shot {i}: frames {first}-{last}, start {}, duration {}, \
+                 MaxCLL: {:.2} nits (frame {}), MaxFALL: {:.2} nits (frame {})",
+                shot.time
+                    .map_or_else(|| "unknown".to_owned(), export::format_timestamp),
+                shot.duration.map_or_else(
+                    || format!("{} frames", shot.stream_frames.len()),
+                    |x| format!("{x:.3}s")
+                ),
+                shot.summary.max_cll,
+                shot.summary.max_cll_frame,
+                shot.summary.max_fall,
+                shot.summary.max_fall_frame,
+            );
+        }
+    }
+
+    if let Some(path) = &args.dovi_l1 {
+        let writer = BufWriter::new(File::create(path)?);
+        dovi::write_generator_config(results, &shots, summary, metadata, writer)?;
+    }
+    if let Some(path) = &args.hdr10plus {
+        hdr10plus::write_json(results, &shots, BufWriter::new(File::create(path)?))?;
+    }
+
+    if let Some(path) = &args.html {
+        let report = report::Report {
+            title: &title,
+            results,
+            summary,
+            percentiles: percentiles.as_ref(),
+            metadata,
+            properties,
+            fps: *fps,
+        };
+        report.write_html(BufWriter::new(File::create(path)?))?;
+    }
+
+    if let Some(output) = &args.output {
+        let timecode_fps = if args.timecode {
+            Some(fps.ok_or("cannot show timecode, the stream has no frame rate")?)
+        } else {
+            None
+        };
+
+        let series = iter::zip(&args.inputs, &measurements)
+            .map(|(input, measurement)| plot::Series {
+                label: &input.label,
+                results: &measurement.results,
+                summary: &measurement.summary,
+                metadata: &measurement.metadata,
+                offset: input.offset,
+            })
+            .collect::<Vec<_>>();
+
+        let options = plot::PlotOptions {
+            output,
+            format: args.format,
+            title: &title,
+            size: args.size,
+            shaded_shots: if args.shade_shots { &shots } else { &[] },
+            percentile: args.plot_percentile,
+            timecode_fps,
+        };
+
+        plot::plot(&series, &options)?;
+    }
 
-#[derive(Debug)]
-struct FrameInfo {
-    max: f64,
-    min: f64,
-    avg: f64,
+    Ok(if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
 }
 
-impl FrameInfo {
-    fn parse_frame(frame: &[u16]) -> Self {
-        let mut sum = 0;
-        let mut max = 0;
-        let mut min = u16::MAX;
+/// Prints how the other input differs from the reference and whether it is within tolerance,
+/// writing the per-frame differences to `--csv` if given. Returns whether it passed.
+fn report_comparison(
+    comparison: &Comparison,
+    reference: &Input,
+    other: &Input,
+    args: &Args,
+) -> Result<bool, Box<dyn Error>> {
+    let normal = args.verbosity >= Verbosity::Normal;
+    let verbose = args.verbosity >= Verbosity::Verbose;
+    let tolerances = &args.tolerances;
+
+    let [Some(mean_max), Some(mean_avg), Some(mean_min)] = Level::ALL.map(|x| comparison.mean(x))
+    else {
+        return Err("no frames of the two inputs line up, try --offset".into());
+    };
+
+    if let Some(path) = &args.csv {
+        let writer = BufWriter::new(File::create(path)?);
+        compare::write_csv(comparison, tolerances, writer)?;
+    }
 
-        for &sample in frame {
-            sum += sample as usize;
-            max = std::cmp::max(max, sample);
-            min = std::cmp::min(min, sample);
+    let (reference_label, other_label) = (&reference.label, &other.label);
+
+    if verbose {
+        for x in &comparison.deltas {
+            println!(
+                "Frame {} ({other_label} frame {}): max {:+.2} nits, avg {:+.2} nits, \
+                 min {:+.6} nits",
+                x.frame,
+                x.other_frame,
+                x.delta(Level::Max),
+                x.delta(Level::Avg),
+                x.delta(Level::Min),
+            );
         }
+    }
 
-        let avg = (sum / frame.len()) as u16;
+    if normal {
+        println!(
+            "== {other_label} compared with {reference_label} ==\n\
+             Compared {} frames ({} only in {reference_label}, {} only in {other_label})",
+            comparison.deltas.len(),
+            comparison.reference_only,
+            comparison.other_only,
+        );
+        println!(
+            "Mean difference: max {mean_max:+.2} nits, avg {mean_avg:+.2} nits, \
+             min {mean_min:+.6} nits"
+        );
+
+        for level in Level::ALL {
+            println!("Largest differences in {}:", level.name());
+            for x in comparison.largest(args.top, level) {
+                println!(
+                    "  Frame {} ({other_label} frame {}): {:.4} -> {:.4} nits ({:+.4})",
+                    x.frame,
+                    x.other_frame,
+                    x.reference.get(level),
+                    x.other.get(level),
+                    x.delta(level),
+                );
+            }
+        }
+    }
 
-        FrameInfo {
-            max: yuv420_10bit_to_pq(max),
-            min: yuv420_10bit_to_pq(min),
-            avg: yuv420_10bit_to_pq(avg),
+    let failures = comparison.failures(tolerances);
+    if args.verbosity > Verbosity::Quiet {
+        println!(
+            "Tolerances: max {}, avg {}, min {}",
+            tolerances.max, tolerances.avg, tolerances.min
+        );
+        match failures.first() {
+            Some(first) => println!(
+                "FAIL: {} of {} frames out of tolerance, the first at frame {}",
+                failures.len(),
+                comparison.deltas.len(),
+                first.frame
+            ),
+            None => println!("PASS: every frame is within tolerance"),
         }
     }
+
+    Ok(failures.is_empty())
 }
 
-fn main() -> Result<(), ffmpeg::Error> {
-    ffmpeg::init()?;
+/// Everything measured about one input.
+struct Measurement {
+    results: Vec<FrameInfo>,
+    percentiles: Option<Percentiles>,
+    summary: Summary,
+    metadata: StaticMetadata,
+    transfer: Transfer,
+    /// Shown in the HTML report
+    properties: Vec<(&'static str, String)>,
+    fps: Option<f64>,
+}
 
-    let input_path = env::args()
-        .nth(1)
-        .expect("Usage: pq_yuv_decoder <video_file>");
+/// Decodes and measures one input, printing what was found.
+fn measure(path: &Path, args: &Args) -> Result<Measurement, Box<dyn Error>> {
+    let normal = args.verbosity >= Verbosity::Normal;
+    let verbose = args.verbosity >= Verbosity::Verbose;
+
+    let mut ictx = format::input(path)?;
+    let input = match args.stream {
+        Some(index) => ictx
+            .stream(index)
+            .ok_or_else(|| format!("input has no stream {index}"))?,
+        None => ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or("input has no video stream")?,
+    };
+
+    let stream = StreamInfo::new(&input);
+    let mut metadata = StaticMetadata::from_stream(&input);
+    let stream_index = stream.index;
+    let codec_params = input.parameters();
+    let codec = codec_params.id();
+    let mut context_decoder = ffmpeg::codec::context::Context::from_parameters(codec_params)?;
 
-    let mut ictx = format::input(&input_path)?;
-    let input = ictx
-        .streams()
-        .best(media::Type::Video)
-        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let mut threading = threading::Config::count(num_cpus::get());
+    threading.kind = threading::Type::Frame;
+    context_decoder.set_threading(threading);
 
-    let num_frames = input
-        .metadata()
-        .get("NUMBER_OF_FRAMES")
-        .and_then(|x| x.parse::<u64>().ok());
-    let frame_rate = input.rate();
+    let mut decoder = context_decoder
+        .decoder()
+        .video()
+        .map_err(|_| format!("stream {stream_index} is not a video stream"))?;
 
-    let stream_index = input.index();
-    let codec_params = input.parameters();
-    let context_decoder = ffmpeg::codec::context::Context::from_parameters(codec_params)?;
-    let mut decoder = context_decoder.decoder().video()?;
+    if args.keyframes {
+        decoder.skip_frame(Discard::NonKey);
+    }
 
-    println!("Input pixel format: {:?}", decoder.format());
-    println!("Width x Height: {} x {}", decoder.width(), decoder.height());
+    if normal {
+        println!("Input pixel format: {:?}", decoder.format());
+        println!("Width x Height: {} x {}", decoder.width(), decoder.height());
+        println!("Colour range: {:?}", decoder.color_range());
+        if let Some(range) = args.range {
+            println!("Overriding colour range: {range:?}");
+        }
+    }
 
-    let mut decoded = Video::empty();
-    let mut frame_count = 0;
+    let trc = decoder.color_transfer_characteristic();
+    let transfer = match args
+        .transfer
+        .or_else(|| Transfer::from_ffmpeg(trc, args.hlg_peak, args.sdr_white))
+    {
+        Some(transfer) => transfer,
+        None => {
+            if args.verbosity > Verbosity::Quiet && trc != TransferCharacteristic::Unspecified {
+                eprintln!("warning: cannot measure transfer characteristics {trc:?}, assuming PQ");
+            }
+            Transfer::Pq
+        }
+    };
+    if normal {
+        println!("Transfer: {transfer}");
+    }
 
-    let mut results: Vec<FrameInfo> = Vec::new();
-    let mut last = Instant::now();
+    if decoder.format() != format::Pixel::None {
+        FrameReader::check_format(decoder.format())?;
+    }
 
-    for (stream, packet) in ictx.packets() {
-        if stream.index() == stream_index {
-            decoder.send_packet(&packet)?;
-
-            while decoder.receive_frame(&mut decoded).is_ok() {
-                frame_count += 1;
-
-                if frame_count % 200 == 0 {
-                    let dur = Instant::now() - last;
-                    let fps = 100.0 / dur.as_secs_f64();
-                    let x = fps / f64::from(frame_rate);
-                    if let Some(num_frames) = num_frames {
-                        println!(
-                            "{:0.02}%, {:.02}fps ({:.03}x)",
-                            frame_count / num_frames,
-                            fps,
-                            x
-                        );
-                    } else {
-                        println!("last 100 frames {:.02}fps ({:.03}x)", fps, x);
-                    }
-                    last = Instant::now();
-                }
+    // Shown in the HTML report
+    let mut properties = vec![
+        ("File", path.display().to_string()),
+        ("Container", ictx.format().description().to_owned()),
+        ("Stream", stream_index.to_string()),
+        ("Codec", codec.name().to_owned()),
+        (
+            "Resolution",
+            format!("{} x {}", decoder.width(), decoder.height()),
+        ),
+        ("Pixel format", format!("{:?}", decoder.format())),
+        ("Colour range", format!("{:?}", decoder.color_range())),
+        (
+            "Colour primaries",
+            format!("{:?}", decoder.color_primaries()),
+        ),
+        (
+            "Transfer characteristics",
+            format!("{:?}", decoder.color_transfer_characteristic()),
+        ),
+        (
+            "Matrix coefficients",
+            format!("{:?}", decoder.color_space()),
+        ),
+    ];
+    if let Some(fps) = stream.fps() {
+        properties.push(("Frame rate", format!("{fps:.3} fps")));
+    }
+    if ictx.duration() > 0 {
+        let duration = ictx.duration() as f64 * f64::from(ffmpeg::rescale::TIME_BASE);
+        properties.push(("Duration", export::format_timestamp(duration)));
+    }
+
+    let options = AnalyzerOptions {
+        ignore_brightest: args.ignore_brightest,
+        percentiles: normal
+            || args.csv.is_some()
+            || args.json.is_some()
+            || args.html.is_some()
+            || args.plot_percentile.is_some(),
+        profiles: args.needs_shots() && args.scene_detector.needs_profiles(),
+        hdr10plus: args.hdr10plus.is_some(),
+        transfer,
+        matrix: Matrix::from_ffmpeg(decoder.color_space()),
+    };
+
+    // Decoding runs ahead of measurement by at most this many frames
+    let (pending_tx, pending_rx) = mpsc::sync_channel(num_cpus::get() * 2);
+
+    let (frame_count, analyzer) = thread::scope(|scope| {
+        let analysis = scope.spawn(move || {
+            // Each worker measures frames into its own analyzer, which are merged at the end
+            pending_rx
+                .into_iter()
+                .par_bridge()
+                .map_init(
+                    FrameReader::default,
+                    |reader, pending: PendingFrame| -> Result<_, FormatError> {
+                        let planes = reader.planes(&pending.video)?;
+                        Ok(options.measure(pending.frame, pending.time, &planes, pending.range))
+                    },
+                )
+                .try_fold(
+                    || Analyzer::new(options),
+                    |mut analyzer, frameinfo| {
+                        analyzer.push(frameinfo?);
+                        Ok::<_, FormatError>(analyzer)
+                    },
+                )
+                .try_reduce(|| Analyzer::new(options), |a, b| Ok(a.merge(b)))
+        });
 
-                // YUV420 10-bit (e.g., yuv420p10le)
-                let y_plane = bytemuck::cast_slice::<u8, u16>(decoded.data(0));
+        let frame_count = decode(
+            &mut ictx,
+            &mut decoder,
+            &stream,
+            args,
+            &mut metadata,
+            pending_tx,
+        );
+        let analyzer = analysis.join().expect("analysis thread panicked");
+
+        (frame_count, analyzer)
+    });
+
+    // A measurement error ends decoding early, so it is the more interesting of the two
+    let Analysis {
+        frames: results,
+        percentiles,
+    } = analyzer?.finish();
+    let frame_count = frame_count?;
+
+    if verbose {
+        for frameinfo in &results {
+            let robust_max = if args.ignore_brightest > 0 {
+                format!(", robust max {:.2} nits", pq_to_nits(frameinfo.robust_max))
+            } else {
+                String::new()
+            };
+
+            println!(
+                "Frame {}: max {:.2} nits, avg {:.2} nits, min {:.6} nits{robust_max}",
+                frameinfo.frame,
+                pq_to_nits(frameinfo.max),
+                pq_to_nits(frameinfo.avg),
+                pq_to_nits(frameinfo.min),
+            );
+        }
+    }
 
-                let frameinfo = FrameInfo::parse_frame(y_plane);
+    if normal {
+        println!("Total decoded frames: {}", frame_count);
+        if results.len() as u64 != frame_count {
+            println!("Total measured frames: {}", results.len());
+        }
+    }
 
-                results.push(frameinfo);
-            }
+    let summary = Summary::new(&results).ok_or("no frames were measured")?;
+
+    if normal {
+        println!(
+            "MaxCLL: {:.2} nits (frame {}), MaxFALL: {:.2} nits (frame {})",
+            summary.max_cll, summary.max_cll_frame, summary.max_fall, summary.max_fall_frame
+        );
+        if args.ignore_brightest > 0 {
+            println!(
+                "MaxCLL ignoring the {} brightest pixels of each frame: {:.2} nits (frame {})",
+                args.ignore_brightest, summary.robust_max_cll, summary.robust_max_cll_frame
+            );
+        }
+
+        if let Some(percentiles) = percentiles {
+            let percentiles = iter::zip(PERCENTILES, percentiles)
+                .map(|(percent, pq)| {
+                    format!("{}: {:.2} nits", percentile_name(percent), pq_to_nits(pq))
+                })
+                .collect::<Vec<_>>();
+            println!("Over all pixels: {}", percentiles.join(", "));
         }
     }
 
-    decoder.send_eof()?;
-    while decoder.receive_frame(&mut decoded).is_ok() {
-        println!("Flushing frame {}", frame_count);
-        frame_count += 1;
+    if normal {
+        match metadata.content_light_level {
+            Some(cll) => println!(
+                "Declared MaxCLL: {} nits, MaxFALL: {} nits",
+                cll.max_cll, cll.max_fall
+            ),
+            None => println!("No Content Light Level metadata declared"),
+        }
+        match metadata.mastering_display {
+            Some(mastering_display) => println!("Declared mastering display: {mastering_display}"),
+            None => println!("No Mastering Display metadata declared"),
+        }
     }
 
-    println!("Total decoded frames: {}", frame_count);
+    if args.verbosity > Verbosity::Quiet {
+        for mismatch in metadata.mismatches(&summary) {
+            eprintln!("warning: metadata mismatch: {mismatch}");
+        }
+    }
 
-    plot(
-        &results,
-        std::path::Path::new("out.png"),
-        "SMPTE 2084 PQ Measurements Plot",
-    );
+    Ok(Measurement {
+        results,
+        percentiles,
+        summary,
+        metadata,
+        transfer,
+        properties,
+        fps: stream.fps(),
+    })
+}
 
-    Ok(())
+/// A decoded frame on its way to be measured.
+struct PendingFrame {
+    frame: usize,
+    time: Option<f64>,
+    video: Video,
+    /// The signal range from `--range` or the stream, if either specifies one.
+    range: Option<SignalRange>,
 }
 
-pub struct PqCoord {}
-
-impl Ranged for PqCoord {
-    type FormatOption = NoDefaultFormatting;
-    type ValueType = f64;
-
-    fn map(&self, value: &f64, limit: (i32, i32)) -> i32 {
-        let size = limit.1 - limit.0;
-        (*value * size as f64) as i32 + limit.0
-    }
-
-    fn key_points<Hint: KeyPointHint>(&self, _hint: Hint) -> Vec<f64> {
-        vec![
-            nits_to_pq(0.01),
-            nits_to_pq(0.1),
-            nits_to_pq(0.5),
-            nits_to_pq(1.0),
-            nits_to_pq(2.5),
-            nits_to_pq(5.0),
-            nits_to_pq(10.0),
-            nits_to_pq(25.0),
-            nits_to_pq(50.0),
-            nits_to_pq(100.0),
-            nits_to_pq(200.0),
-            nits_to_pq(400.0),
-            nits_to_pq(600.0),
-            nits_to_pq(1000.0),
-            nits_to_pq(2000.0),
-            nits_to_pq(4000.0),
-            nits_to_pq(10000.0),
-        ]
-    }
-
-    fn range(&self) -> Range<f64> {
-        0_f64..10000.0_f64
-    }
+/// What `decode` needs to know about the stream being measured.
+struct StreamInfo {
+    index: usize,
+    num_frames: Option<u64>,
+    frame_rate: ffmpeg::Rational,
+    time_base: ffmpeg::Rational,
+    /// Timestamp of the start of the stream, in `time_base` units.
+    start_time: i64,
 }
-impl ValueFormatter<f64> for PqCoord {
-    fn format_ext(&self, value: &f64) -> String {
-        let nits = (pq_to_nits(*value) * 1000.0).round() / 1000.0;
-        format!("{nits}")
+
+impl StreamInfo {
+    fn new(stream: &ffmpeg::Stream) -> Self {
+        let num_frames = stream
+            .metadata()
+            .get("NUMBER_OF_FRAMES")
+            .and_then(|x| x.parse::<u64>().ok());
+
+        StreamInfo {
+            index: stream.index(),
+            num_frames,
+            frame_rate: stream.rate(),
+            time_base: stream.time_base(),
+            // AV_NOPTS_VALUE when the container doesn't say
+            start_time: Some(stream.start_time())
+                .filter(|&x| x != i64::MIN)
+                .unwrap_or(0),
+        }
+    }
+
+    /// Converts a timestamp to seconds since the start of the stream.
+    fn seconds(&self, timestamp: i64) -> f64 {
+        (timestamp - self.start_time) as f64 * f64::from(self.time_base)
+    }
+
+    /// The frame rate, if the stream has a usable one.
+    fn fps(&self) -> Option<f64> {
+        Some(f64::from(self.frame_rate)).filter(|x| x.is_finite() && *x > 0.0)
+    }
+
+    /// Converts a time since the start of the stream to a timestamp in `AV_TIME_BASE` units,
+    /// as `Input::seek` takes.
+    fn seek_timestamp(&self, seconds: f64) -> i64 {
+        let start = self.start_time as f64 * f64::from(self.time_base);
+        ((start + seconds) * f64::from(ffmpeg::rescale::TIME_BASE).recip()) as i64
     }
 }
 
-fn plot(results: &[FrameInfo], output: &std::path::Path, title: &str) {
-    let root = BitMapBackend::new(output, (3000, 1200)).into_drawing_area();
-    root.fill(&WHITE).unwrap();
-    let root = root
-        .margin(30, 30, 60, 60)
-        .titled(title, ("sans-serif", 40))
-        .unwrap();
-
-    let x_spec = 0..results.len();
-
-    let mut chart = ChartBuilder::on(&root)
-        .x_label_area_size(60)
-        .y_label_area_size(60)
-        .margin_top(90)
-        .build_cartesian_2d(x_spec, PqCoord {})
-        .unwrap();
-
-    chart
-        .configure_mesh()
-        .bold_line_style(BLACK.mix(0.10))
-        .light_line_style(BLACK.mix(0.01))
-        .label_style(("sans-serif", 22))
-        .axis_desc_style(("sans-serif", 24))
-        .x_desc("frames")
-        .x_max_light_lines(1)
-        .x_labels(24)
-        .y_desc("nits (cd/m²)")
-        .draw()
-        .unwrap();
-
-    let maxfall = pq_to_nits(results.iter().map(|x| FloatOrd(x.avg)).max().unwrap().0);
-    let maxfall_avg = pq_to_nits(results.iter().map(|x| x.avg).sum::<f64>() / results.len() as f64);
-
-    let avg_series_label = format!(
-        "Average (MaxFALL: {:.2} nits, avg: {:.2} nits)",
-        maxfall, maxfall_avg
-    );
-
-    let maxcll = pq_to_nits(results.iter().map(|x| FloatOrd(x.max)).max().unwrap().0);
-    let maxcll_avg = pq_to_nits(results.iter().map(|x| x.max).sum::<f64>() / results.len() as f64);
-
-    let max_series_label = format!(
-        "Maximum (MaxCLL: {:.2} nits, avg: {:.2} nits)",
-        maxcll, maxcll_avg,
-    );
-
-    let max_min = pq_to_nits(results.iter().map(|x| FloatOrd(x.min)).max().unwrap().0);
-    let min_series_label = format!("Minimum (max: {:.6} nits)", max_min);
-
-    let max_series = AreaSeries::new(
-        (0..).zip(results).map(|(x, y)| (x, (y.max))),
-        0.0,
-        MAX_COLOUR.mix(0.25),
-    )
-    .border_style(MAX_COLOUR);
-    let avg_series = AreaSeries::new(
-        (0..).zip(results).map(|(x, y)| (x, (y.avg))),
-        0.0,
-        AVERAGE_COLOUR.mix(0.25),
-    )
-    .border_style(AVERAGE_COLOUR);
-    let min_series = AreaSeries::new(
-        (0..).zip(results).map(|(x, y)| (x, (y.min))),
-        0.0,
-        BLACK.mix(0.25),
-    )
-    .border_style(BLACK);
-
-    chart
-        .draw_series(max_series)
-        .unwrap()
-        .label(max_series_label)
-        .legend(|(x, y)| {
-            PathElement::new(
-                vec![(x, y), (x + 20, y)],
-                ShapeStyle {
-                    color: MAX_COLOUR.to_rgba(),
-                    filled: false,
-                    stroke_width: 2,
-                },
-            )
-        });
+/// Decodes the frames of the selected stream and hands those in the requested range to
+/// `pending`, returning the number of frames decoded. Static metadata the container lacks is
+/// filled in from frame side data.
+fn decode(
+    ictx: &mut format::context::Input,
+    decoder: &mut ffmpeg::decoder::Video,
+    stream: &StreamInfo,
+    args: &Args,
+    metadata: &mut StaticMetadata,
+    pending: mpsc::SyncSender<PendingFrame>,
+) -> Result<u64, Box<dyn Error>> {
+    let normal = args.verbosity >= Verbosity::Normal;
+    let verbose = args.verbosity >= Verbosity::Verbose;
+
+    let seek_to = match args.start {
+        Some(Position::Time(seconds)) => Some(seconds),
+        Some(Position::Frame(frame)) => stream.fps().map(|fps| frame as f64 / fps),
+        None => None,
+    };
+
+    if let Some(seconds) = seek_to.filter(|&x| x > 0.0) {
+        // Lands on the last keyframe at or before the start, which is decoded up to it
+        let timestamp = stream.seek_timestamp(seconds);
+        ictx.seek(timestamp, ..timestamp)?;
+    }
 
-    chart
-        .draw_series(avg_series)
-        .unwrap()
-        .label(avg_series_label)
-        .legend(|(x, y)| {
-            PathElement::new(
-                vec![(x, y), (x + 20, y)],
-                ShapeStyle {
-                    color: AVERAGE_COLOUR.to_rgba(),
-                    filled: false,
-                    stroke_width: 2,
-                },
-            )
-        });
+    // Frames that are never decoded can't be counted, so once any are skipped, frames are
+    // numbered from their timestamps instead. Counting only the decoded frames would leave
+    // frame numbers, and the plot's x-axis, short of where the frames are in the stream.
+    let number_by_time = seek_to.is_some_and(|x| x > 0.0) || args.keyframes;
+    let fps = stream.fps();
+    if number_by_time && fps.is_none() {
+        return Err("--start and --keyframes need the stream's frame rate to number frames".into());
+    }
 
-    chart
-        .draw_series(min_series)
-        .unwrap()
-        .label(min_series_label)
-        .legend(|(x, y)| {
-            PathElement::new(
-                vec![(x, y), (x + 20, y)],
-                ShapeStyle {
-                    color: MIN_COLOUR.to_rgba(),
-                    filled: false,
-                    stroke_width: 2,
-                },
-            )
-        });
+    let mut untimed = false;
+    let mut frame_count = 0;
+    let mut sampled = 0;
+    let mut last = Instant::now();
+
+    // Hands on every frame the decoder has ready, breaking once no more frames are wanted
+    let mut receive_frames = |decoder: &mut ffmpeg::decoder::Video| -> ControlFlow<()> {
+        loop {
+            let mut decoded = Video::empty();
+            if decoder.receive_frame(&mut decoded).is_err() {
+                return ControlFlow::Continue(());
+            }
+
+            let time = decoded.timestamp().map(|ts| stream.seconds(ts));
+            let frame = match (number_by_time, time, fps) {
+                (true, Some(time), Some(fps)) => (time * fps).round().max(0.0) as usize,
+                (true, ..) => {
+                    untimed = true;
+                    return ControlFlow::Break(());
+                }
+                (false, ..) => frame_count as usize,
+            };
+            frame_count += 1;
+
+            let past_end = match args.end {
+                Some(Position::Frame(end)) => frame >= end,
+                Some(Position::Time(end)) => time.is_some_and(|time| time >= end),
+                None => false,
+            };
+            if past_end {
+                return ControlFlow::Break(());
+            }
+
+            if normal && frame_count % 200 == 0 {
+                let dur = Instant::now() - last;
+                let fps = 100.0 / dur.as_secs_f64();
+                let x = fps / f64::from(stream.frame_rate);
+                if let Some(num_frames) = stream.num_frames {
+                    println!(
+                        "{:0.02}%, {:.02}fps ({:.03}x)",
+                        frame_count / num_frames,
+                        fps,
+                        x
+                    );
+                } else {
+                    println!("last 100 frames {:.02}fps ({:.03}x)", fps, x);
+                }
+                last = Instant::now();
+            }
+
+            if !metadata.is_complete() {
+                metadata.update_from_frame(&decoded);
+            }
 
-    chart
-        .configure_series_labels()
-        .border_style(MIN_COLOUR)
-        .position(SeriesLabelPosition::LowerLeft)
-        .label_font(("sans-serif", 24))
-        .background_style(WHITE)
-        .draw()
-        .unwrap();
+            // Decoders may not honour skipping non-key frames
+            if args.keyframes && !decoded.is_key() {
+                continue;
+            }
+
+            let before_start = match args.start {
+                Some(Position::Frame(start)) => frame < start,
+                Some(Position::Time(start)) => time.is_some_and(|time| time < start),
+                None => false,
+            };
+            if before_start {
+                continue;
+            }
 
-    let chart_caption = format!("Frames: {}", results.len());
+            sampled += 1;
+            if (sampled - 1) % args.every != 0 {
+                continue;
+            }
+
+            let range = args
+                .range
+                .or_else(|| SignalRange::from_ffmpeg(decoded.color_range()))
+                .or_else(|| SignalRange::from_ffmpeg(decoder.color_range()));
+
+            let pending_frame = PendingFrame {
+                frame,
+                time,
+                video: decoded,
+                range,
+            };
+
+            // The receiver only hangs up if measuring failed
+            if pending.send(pending_frame).is_err() {
+                return ControlFlow::Break(());
+            }
+        }
+    };
+
+    let finished_early = 'decode: {
+        for (packet_stream, packet) in ictx.packets() {
+            if packet_stream.index() == stream.index {
+                decoder.send_packet(&packet)?;
+
+                if receive_frames(decoder).is_break() {
+                    break 'decode true;
+                }
+            }
+        }
+        false
+    };
+
+    if !finished_early {
+        // Drain the frames still buffered in the decoder, which are measured like any other
+        decoder.send_eof()?;
+        if verbose {
+            println!("Flushing decoder");
+        }
+        let _ = receive_frames(decoder);
+    }
+
+    if untimed {
+        return Err("--start and --keyframes need frame timestamps to number frames".into());
+    }
 
-    let caption_style = ("sans-serif", 24).into_text_style(&root);
-    root.draw_text(&chart_caption, &caption_style, (60, 10))
-        .unwrap();
+    Ok(frame_count)
 }
@@ -0,0 +1,331 @@
+use ffmpeg::{Stream, codec::packet, frame, util::frame::Frame};
+use ffmpeg_next as ffmpeg;
+use std::fmt;
+
+use crate::Summary;
+
+/// Content Light Level Information, as in CTA-861.3 and HEVC SEI. Both values are in nits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    pub max_cll: u32,
+    pub max_fall: u32,
+}
+
+impl ContentLightLevel {
+    /// The Content Light Level to declare for measured content. CTA-861.3 counts whole nits in
+    /// 16 bits, and the measurements are rounded up so that the declaration is an upper bound.
+    pub fn from_summary(summary: &Summary) -> Self {
+        let nits = |x: f64| x.ceil().clamp(0.0, u16::MAX as f64) as u32;
+
+        ContentLightLevel {
+            max_cll: nits(summary.max_cll),
+            max_fall: nits(summary.max_fall),
+        }
+    }
+
+    /// Parses an `AVContentLightMetadata`.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let field = |i: usize| {
+            Some(u32::from_ne_bytes(
+                data.get(i * 4..i * 4 + 4)?.try_into().ok()?,
+            ))
+        };
+
+        Some(ContentLightLevel {
+            max_cll: field(0)?,
+            max_fall: field(1)?,
+        })
+    }
+}
+
+/// A CIE 1931 xy chromaticity.
+pub type Chromaticity = [f64; 2];
+
+/// Mastering Display Colour Volume, as in SMPTE ST 2086. Chromaticities are CIE 1931 xy, and
+/// luminances are in nits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MasteringDisplay {
+    /// The red, green and blue primaries, if known.
+    pub primaries: Option<[Chromaticity; 3]>,
+    pub white_point: Option<Chromaticity>,
+    /// The minimum and maximum luminance of the display, if known.
+    pub luminance: Option<(f64, f64)>,
+}
+
+impl MasteringDisplay {
+    /// Parses an `AVMasteringDisplayMetadata`, which is 10 `AVRational`s followed by the
+    /// `has_primaries` and `has_luminance` flags.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let int = |i: usize| {
+            Some(i32::from_ne_bytes(
+                data.get(i * 4..i * 4 + 4)?.try_into().ok()?,
+            ))
+        };
+        let rational = |i: usize| -> Option<f64> {
+            let (num, den) = (int(2 * i)?, int(2 * i + 1)?);
+            Some(if den == 0 {
+                0.0
+            } else {
+                num as f64 / den as f64
+            })
+        };
+
+        let has_primaries = int(20)? != 0;
+        let has_luminance = int(21)? != 0;
+
+        let primaries = [
+            [rational(0)?, rational(1)?],
+            [rational(2)?, rational(3)?],
+            [rational(4)?, rational(5)?],
+        ];
+        let white_point = [rational(6)?, rational(7)?];
+        let luminance = (rational(8)?, rational(9)?);
+
+        Some(MasteringDisplay {
+            primaries: has_primaries.then_some(primaries),
+            white_point: has_primaries.then_some(white_point),
+            luminance: has_luminance.then_some(luminance),
+        })
+    }
+}
+
+impl fmt::Display for MasteringDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (Some([r, g, b]), Some(wp)) = (self.primaries, self.white_point) {
+            write!(
+                f,
+                "R({:.4}, {:.4}) G({:.4}, {:.4}) B({:.4}, {:.4}) WP({:.4}, {:.4})",
+                r[0], r[1], g[0], g[1], b[0], b[1], wp[0], wp[1]
+            )?;
+            if self.luminance.is_some() {
+                write!(f, ", ")?;
+            }
+        }
+        if let Some((min, max)) = self.luminance {
+            write!(f, "L({min:.4}, {max:.1}) nits")?;
+        }
+        Ok(())
+    }
+}
+
+/// The HDR10 static metadata declared by a file, from the container or the bitstream.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StaticMetadata {
+    pub content_light_level: Option<ContentLightLevel>,
+    pub mastering_display: Option<MasteringDisplay>,
+}
+
+impl StaticMetadata {
+    /// Reads the metadata attached to a stream by the demuxer.
+    pub fn from_stream(stream: &Stream) -> Self {
+        let mut metadata = StaticMetadata::default();
+
+        for side_data in stream.side_data() {
+            match side_data.kind() {
+                packet::side_data::Type::ContentLightLevel => {
+                    metadata.content_light_level = ContentLightLevel::parse(side_data.data());
+                }
+                packet::side_data::Type::MasteringDisplayMetadata => {
+                    metadata.mastering_display = MasteringDisplay::parse(side_data.data());
+                }
+                _ => {}
+            }
+        }
+
+        metadata
+    }
+
+    /// Whether there is nothing left that frame side data could fill in.
+    pub fn is_complete(&self) -> bool {
+        self.content_light_level.is_some() && self.mastering_display.is_some()
+    }
+
+    /// Fills in anything the container didn't declare from a decoded frame's side data, which
+    /// is where the bitstream's SEI messages end up.
+    pub fn update_from_frame(&mut self, frame: &Frame) {
+        if self.content_light_level.is_none() {
+            self.content_light_level = frame
+                .side_data(frame::side_data::Type::ContentLightLevel)
+                .and_then(|side_data| ContentLightLevel::parse(side_data.data()));
+        }
+        if self.mastering_display.is_none() {
+            self.mastering_display = frame
+                .side_data(frame::side_data::Type::MasteringDisplayMetadata)
+                .and_then(|side_data| MasteringDisplay::parse(side_data.data()));
+        }
+    }
+
+    /// Describes every way in which the declared metadata disagrees with the measurements.
+    pub fn mismatches(&self, summary: &Summary) -> Vec<String> {
+        // Declared values are whole nits, and encoders round differently. CTA-861.3 defines 0 as
+        // unknown, which many encoders write, so it is never a mismatch.
+        fn differs(declared: u32, measured: f64) -> bool {
+            let difference = (declared as f64 - measured).abs();
+            declared != 0 && difference > 1.0 && difference > measured * 0.01
+        }
+
+        let mut mismatches = Vec::new();
+
+        if let Some(cll) = self.content_light_level {
+            if differs(cll.max_cll, summary.max_cll) {
+                mismatches.push(format!(
+                    "declared MaxCLL {} nits, measured {:.0} nits",
+                    cll.max_cll, summary.max_cll
+                ));
+            }
+            if differs(cll.max_fall, summary.max_fall) {
+                mismatches.push(format!(
+                    "declared MaxFALL {} nits, measured {:.0} nits",
+                    cll.max_fall, summary.max_fall
+                ));
+            }
+        }
+
+        if let Some((_, max_luminance)) = self.mastering_display.and_then(|x| x.luminance)
+            && summary.max_cll > max_luminance + 1.0
+        {
+            mismatches.push(format!(
+                "measured MaxCLL {:.0} nits exceeds the mastering display's {:.0} nits",
+                summary.max_cll, max_luminance
+            ));
+        }
+
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(fields: &[i32]) -> Vec<u8> {
+        fields.iter().flat_map(|x| x.to_ne_bytes()).collect()
+    }
+
+    /// An `AVMasteringDisplayMetadata` for a P3-D65 display of 0.0001 to 1000 nits.
+    fn mastering_display(has_primaries: i32, has_luminance: i32) -> Vec<u8> {
+        let rationals = [
+            (34000, 50000), // Red x
+            (16000, 50000),
+            (13250, 50000), // Green x
+            (34500, 50000),
+            (7500, 50000), // Blue x
+            (3000, 50000),
+            (15635, 50000), // White point x
+            (16450, 50000),
+            (1, 10000), // Minimum luminance
+            (10000000, 10000),
+        ];
+
+        let mut fields = rationals
+            .into_iter()
+            .flat_map(|(num, den)| [num, den])
+            .collect::<Vec<_>>();
+        fields.extend([has_primaries, has_luminance]);
+        bytes(&fields)
+    }
+
+    #[test]
+    fn content_light_level() {
+        assert_eq!(
+            ContentLightLevel::parse(&bytes(&[1000, 400])),
+            Some(ContentLightLevel {
+                max_cll: 1000,
+                max_fall: 400
+            })
+        );
+        assert_eq!(ContentLightLevel::parse(&bytes(&[1000])), None);
+    }
+
+    #[test]
+    fn mastering_display_metadata() {
+        let parsed = MasteringDisplay::parse(&mastering_display(1, 1)).unwrap();
+
+        assert_eq!(
+            parsed.primaries,
+            Some([[0.68, 0.32], [0.265, 0.69], [0.15, 0.06]])
+        );
+        assert_eq!(parsed.white_point, Some([0.3127, 0.329]));
+        assert_eq!(parsed.luminance, Some((0.0001, 1000.0)));
+        assert_eq!(
+            parsed.to_string(),
+            "R(0.6800, 0.3200) G(0.2650, 0.6900) B(0.1500, 0.0600) WP(0.3127, 0.3290), \
+             L(0.0001, 1000.0) nits"
+        );
+    }
+
+    #[test]
+    fn mastering_display_flags() {
+        let parsed = MasteringDisplay::parse(&mastering_display(0, 1)).unwrap();
+        assert_eq!(parsed.primaries, None);
+        assert_eq!(parsed.white_point, None);
+        assert_eq!(parsed.to_string(), "L(0.0001, 1000.0) nits");
+
+        let parsed = MasteringDisplay::parse(&mastering_display(1, 0)).unwrap();
+        assert!(parsed.primaries.is_some());
+        assert_eq!(parsed.luminance, None);
+    }
+
+    #[test]
+    fn mastering_display_zero_denominator() {
+        let mut data = mastering_display(1, 1);
+        // The red x denominator
+        data[4..8].copy_from_slice(&0i32.to_ne_bytes());
+
+        let primaries = MasteringDisplay::parse(&data).unwrap().primaries.unwrap();
+        assert_eq!(primaries[0], [0.0, 0.32]);
+    }
+
+    #[test]
+    fn mismatches() {
+        let results = [crate::FrameInfo::uniform(0, 0.75)];
+        let summary = Summary::new(&results).unwrap();
+        let metadata = |max_cll, max_fall| StaticMetadata {
+            content_light_level: Some(ContentLightLevel { max_cll, max_fall }),
+            mastering_display: None,
+        };
+
+        // 0.75 in PQ is 983 nits
+        assert!(metadata(984, 983).mismatches(&summary).is_empty());
+        assert_eq!(
+            metadata(1000, 400).mismatches(&summary),
+            [
+                "declared MaxCLL 1000 nits, measured 983 nits",
+                "declared MaxFALL 400 nits, measured 983 nits"
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_content_light_level() {
+        let results = [crate::FrameInfo::uniform(0, 0.75)];
+        let summary = Summary::new(&results).unwrap();
+        let metadata = StaticMetadata {
+            content_light_level: Some(ContentLightLevel {
+                max_cll: 0,
+                max_fall: 0,
+            }),
+            mastering_display: None,
+        };
+
+        assert!(metadata.mismatches(&summary).is_empty());
+
+        let metadata = StaticMetadata {
+            content_light_level: Some(ContentLightLevel {
+                max_cll: 0,
+                max_fall: 400,
+            }),
+            ..metadata
+        };
+        assert_eq!(
+            metadata.mismatches(&summary),
+            ["declared MaxFALL 400 nits, measured 983 nits"]
+        );
+    }
+
+    #[test]
+    fn truncated_mastering_display() {
+        let data = mastering_display(1, 1);
+        assert_eq!(MasteringDisplay::parse(&data[..data.len() - 4]), None);
+    }
+}
@@ -0,0 +1,498 @@
+use plotters_backend::{
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingBackend, DrawingErrorKind,
+    FontStyle, FontTransform,
+    text_anchor::{HPos, VPos},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Control point distance for approximating a quarter circle with a cubic Bézier curve.
+const BEZIER_CIRCLE: f64 = 0.552_284_75;
+
+/// A plotters backend that writes a single-page vector PDF, one point per pixel.
+///
+/// Text is set in the standard Helvetica fonts, which every PDF reader provides, so no fonts are
+/// embedded. Characters outside Latin-1 are replaced with `?`.
+pub struct PdfBackend {
+    path: PathBuf,
+    size: (u32, u32),
+    /// The page's content stream.
+    content: Vec<u8>,
+    /// Every distinct opacity used, in thousandths, each of which needs a graphics state.
+    opacities: Vec<u16>,
+}
+
+impl PdfBackend {
+    pub fn new(path: &Path, size: (u32, u32)) -> Self {
+        // Flip the y-axis, so that the origin is at the top left as plotters expects
+        let content = format!("1 0 0 -1 0 {} cm 1 J 1 j\n", size.1).into_bytes();
+
+        PdfBackend {
+            path: path.to_owned(),
+            size,
+            content,
+            opacities: Vec::new(),
+        }
+    }
+
+    /// Selects the fill or stroke colour and opacity for what is drawn next.
+    fn set_colour(&mut self, colour: BackendColor, stroke: bool) -> io::Result<()> {
+        let opacity = (colour.alpha.clamp(0.0, 1.0) * 1000.0).round() as u16;
+        let state = match self.opacities.iter().position(|&x| x == opacity) {
+            Some(state) => state,
+            None => {
+                self.opacities.push(opacity);
+                self.opacities.len() - 1
+            }
+        };
+
+        let (r, g, b) = colour.rgb;
+        let operator = if stroke { "RG" } else { "rg" };
+        writeln!(
+            self.content,
+            "/GS{state} gs {:.4} {:.4} {:.4} {operator}",
+            r as f64 / 255.0,
+            g as f64 / 255.0,
+            b as f64 / 255.0,
+        )
+    }
+
+    fn set_stroke<S: BackendStyle>(&mut self, style: &S) -> io::Result<()> {
+        self.set_colour(style.color(), true)?;
+        writeln!(self.content, "{} w", style.stroke_width())
+    }
+
+    /// Adds a path through `points` to the content stream, without painting it.
+    fn path(&mut self, points: impl IntoIterator<Item = BackendCoord>) -> io::Result<()> {
+        for (i, (x, y)) in points.into_iter().enumerate() {
+            let operator = if i == 0 { "m" } else { "l" };
+            writeln!(self.content, "{x} {y} {operator}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the document, with the page as the last object.
+    fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+        ];
+
+        // Objects 4 and 5 are the fonts, then one graphics state per opacity, then the content
+        let graphics_states: String = (0..self.opacities.len())
+            .map(|i| format!("/GS{i} {} 0 R ", 6 + i))
+            .collect();
+        let content_object = 6 + self.opacities.len();
+
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 4 0 R /F2 5 0 R >> /ExtGState << {graphics_states}>> >> \
+             /Contents {content_object} 0 R >>",
+            self.size.0, self.size.1
+        ));
+        for font in ["Helvetica", "Helvetica-Bold"] {
+            objects.push(format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{font} /Encoding /WinAnsiEncoding >>"
+            ));
+        }
+        for opacity in &self.opacities {
+            let opacity = *opacity as f64 / 1000.0;
+            objects.push(format!(
+                "<< /Type /ExtGState /ca {opacity} /CA {opacity} >>"
+            ));
+        }
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            writeln!(pdf, "{} 0 obj\n{object}\nendobj", i + 1)?;
+        }
+
+        offsets.push(pdf.len());
+        writeln!(
+            pdf,
+            "{content_object} 0 obj\n<< /Length {} >>\nstream",
+            self.content.len()
+        )?;
+        pdf.extend(&self.content);
+        writeln!(pdf, "\nendstream\nendobj")?;
+
+        let xref = pdf.len();
+        writeln!(pdf, "xref\n0 {}\n0000000000 65535 f ", offsets.len() + 1)?;
+        for offset in offsets.iter() {
+            writeln!(pdf, "{offset:010} 00000 n ")?;
+        }
+        writeln!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF",
+            offsets.len() + 1
+        )?;
+
+        writer.write_all(&pdf)?;
+        writer.flush()
+    }
+}
+
+/// Encodes text as a PDF string literal in WinAnsiEncoding, which matches Latin-1 for
+/// printable characters.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut string = vec![b'('];
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => string.extend([b'\\', c as u8]),
+            ' '..='~' | '\u{a0}'..='\u{ff}' => string.push(c as u32 as u8),
+            _ => string.push(b'?'),
+        }
+    }
+    string.push(b')');
+    string
+}
+
+impl DrawingBackend for PdfBackend {
+    type ErrorType = io::Error;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        File::create(&self.path)
+            .and_then(|file| self.write(BufWriter::new(file)))
+            .map_err(DrawingErrorKind::DrawingError)
+    }
+
+    fn draw_pixel(
+        &mut self,
+        (x, y): BackendCoord,
+        colour: BackendColor,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if colour.alpha > 0.0 {
+            self.set_colour(colour, false)
+                .map_err(DrawingErrorKind::DrawingError)?;
+            writeln!(self.content, "{x} {y} 1 1 re f").map_err(DrawingErrorKind::DrawingError)?;
+        }
+        Ok(())
+    }
+
+    fn draw_line<S: BackendStyle>(
+        &mut self,
+        from: BackendCoord,
+        to: BackendCoord,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        self.draw_path([from, to], style)
+    }
+
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        (x0, y0): BackendCoord,
+        (x1, y1): BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+
+        let operator = if fill {
+            self.set_colour(style.color(), false)
+                .map_err(DrawingErrorKind::DrawingError)?;
+            "f"
+        } else {
+            self.set_stroke(style)
+                .map_err(DrawingErrorKind::DrawingError)?;
+            "S"
+        };
+        writeln!(
+            self.content,
+            "{x0} {y0} {} {} re {operator}",
+            x1 - x0,
+            y1 - y0
+        )
+        .map_err(DrawingErrorKind::DrawingError)?;
+        Ok(())
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        path: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+
+        self.set_stroke(style)
+            .map_err(DrawingErrorKind::DrawingError)?;
+        self.path(path).map_err(DrawingErrorKind::DrawingError)?;
+        writeln!(self.content, "S").map_err(DrawingErrorKind::DrawingError)?;
+        Ok(())
+    }
+
+    fn draw_circle<S: BackendStyle>(
+        &mut self,
+        (x, y): BackendCoord,
+        radius: u32,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+
+        let operator = if fill {
+            self.set_colour(style.color(), false)
+                .map_err(DrawingErrorKind::DrawingError)?;
+            "f"
+        } else {
+            self.set_stroke(style)
+                .map_err(DrawingErrorKind::DrawingError)?;
+            "S"
+        };
+
+        let (x, y, r) = (x as f64, y as f64, radius as f64);
+        let k = r * BEZIER_CIRCLE;
+        writeln!(
+            self.content,
+            "{} {y} m \
+             {} {} {} {} {x} {} c \
+             {} {} {} {} {} {y} c \
+             {} {} {} {} {x} {} c \
+             {} {} {} {} {} {y} c h {operator}",
+            x + r,
+            x + r,
+            y + k,
+            x + k,
+            y + r,
+            y + r,
+            x - k,
+            y + r,
+            x - r,
+            y + k,
+            x - r,
+            x - r,
+            y - k,
+            x - k,
+            y - r,
+            y - r,
+            x + k,
+            y - r,
+            x + r,
+            y - k,
+            x + r,
+        )
+        .map_err(DrawingErrorKind::DrawingError)?;
+        Ok(())
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        vert: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+
+        self.set_colour(style.color(), false)
+            .map_err(DrawingErrorKind::DrawingError)?;
+        self.path(vert).map_err(DrawingErrorKind::DrawingError)?;
+        writeln!(self.content, "h f").map_err(DrawingErrorKind::DrawingError)?;
+        Ok(())
+    }
+
+    fn draw_text<TStyle: BackendTextStyle>(
+        &mut self,
+        text: &str,
+        style: &TStyle,
+        (x, y): BackendCoord,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if style.color().alpha == 0.0 {
+            return Ok(());
+        }
+
+        let size = style.size();
+        let (width, _) = self.estimate_text_size(text, style)?;
+        let width = width as f64;
+
+        // Offset from the anchor to the start of the baseline, before rotation. Helvetica's
+        // ascender is about 0.72 em and its descender about 0.21 em.
+        let dx = match style.anchor().h_pos {
+            HPos::Left => 0.0,
+            HPos::Center => -width / 2.0,
+            HPos::Right => -width,
+        };
+        let dy = match style.anchor().v_pos {
+            VPos::Top => 0.72 * size,
+            VPos::Center => 0.255 * size,
+            VPos::Bottom => -0.21 * size,
+        };
+
+        // Clockwise rotation, in the flipped coordinate system
+        let (cos, sin) = match style.transform() {
+            FontTransform::None => (1.0, 0.0),
+            FontTransform::Rotate90 => (0.0, 1.0),
+            FontTransform::Rotate180 => (-1.0, 0.0),
+            FontTransform::Rotate270 => (0.0, -1.0),
+        };
+        let origin = (
+            x as f64 + dx * cos - dy * sin,
+            y as f64 + dx * sin + dy * cos,
+        );
+
+        let font = match style.style() {
+            FontStyle::Bold => "F2",
+            _ => "F1",
+        };
+
+        self.set_colour(style.color(), false)
+            .map_err(DrawingErrorKind::DrawingError)?;
+        // Glyphs are drawn y-up, so the text matrix flips them back upright
+        write!(
+            self.content,
+            "BT /{font} {size} Tf {cos} {sin} {sin} {} {:.2} {:.2} Tm ",
+            -cos, origin.0, origin.1
+        )
+        .map_err(DrawingErrorKind::DrawingError)?;
+        self.content.extend(pdf_string(text));
+        writeln!(self.content, " Tj ET").map_err(DrawingErrorKind::DrawingError)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plotters::style::{BLACK, BLUE, Color, RED, WHITE};
+
+    /// A page with a little of every shape, in three opacities.
+    fn drawing() -> PdfBackend {
+        let mut backend = PdfBackend::new(Path::new("unused.pdf"), (200, 100));
+
+        backend
+            .draw_rect((0, 0), (200, 100), &WHITE.filled(), true)
+            .unwrap();
+        backend
+            .draw_path(
+                [(10, 10), (50, 80), (90, 20)],
+                &Color::stroke_width(&BLUE, 2),
+            )
+            .unwrap();
+        backend
+            .draw_circle((100, 50), 10, &RED.mix(0.5).filled(), true)
+            .unwrap();
+        backend
+            .fill_polygon([(0, 0), (10, 0), (5, 5)], &BLACK.mix(0.25))
+            .unwrap();
+        backend.draw_pixel((3, 4), BLUE.to_backend_color()).unwrap();
+
+        backend
+    }
+
+    fn document(backend: &PdfBackend) -> String {
+        let mut pdf = Vec::new();
+        backend.write(&mut pdf).unwrap();
+        // Nothing drawn has text, so the document is ASCII
+        String::from_utf8(pdf).unwrap()
+    }
+
+    /// The number following `key` in `text`.
+    fn number_after(text: &str, key: &str) -> usize {
+        let start = text.find(key).unwrap() + key.len();
+        let digits = text[start..].split(|c: char| !c.is_ascii_digit()).next();
+        digits.unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn xref_offsets() {
+        let pdf = document(&drawing());
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+
+        let xref = number_after(&pdf, "startxref\n");
+        let mut lines = pdf[xref..].lines();
+        assert_eq!(lines.next(), Some("xref"));
+
+        // Catalog, pages, page, two fonts, three graphics states and the content
+        let size = number_after(lines.next().unwrap(), "0 ");
+        assert_eq!(size, 10);
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+
+        for object in 1..size {
+            let entry = lines.next().unwrap();
+            assert_eq!(entry.len(), 19, "{entry:?}");
+            let offset: usize = entry.strip_suffix(" 00000 n ").unwrap().parse().unwrap();
+            assert!(
+                pdf[offset..].starts_with(&format!("{object} 0 obj\n")),
+                "object {object} is not at {offset}"
+            );
+        }
+
+        assert_eq!(lines.next(), Some("trailer"));
+        assert_eq!(
+            lines.next(),
+            Some(format!("<< /Size {size} /Root 1 0 R >>").as_str())
+        );
+    }
+
+    #[test]
+    fn objects() {
+        let pdf = document(&drawing());
+
+        // Every object is closed before the next opens
+        for object in 1..10 {
+            let header = format!("\n{object} 0 obj\n");
+            let start = pdf.find(&header).unwrap() + header.len();
+            let end = start + pdf[start..].find("\nendobj\n").unwrap();
+            assert!(!pdf[start..end].contains(" obj\n"), "object {object}");
+        }
+
+        assert!(pdf.contains("/ExtGState << /GS0 6 0 R /GS1 7 0 R /GS2 8 0 R >>"));
+        assert!(pdf.contains("7 0 obj\n<< /Type /ExtGState /ca 0.5 /CA 0.5 >>"));
+        assert!(pdf.contains("/MediaBox [0 0 200 100]"));
+        assert!(pdf.contains("/Contents 9 0 R"));
+    }
+
+    #[test]
+    fn content_stream_length() {
+        let pdf = document(&drawing());
+
+        let start = pdf.find("\nstream\n").unwrap() + "\nstream\n".len();
+        let end = pdf.find("\nendstream\n").unwrap();
+        assert_eq!(number_after(&pdf, "/Length "), end - start);
+        assert!(pdf[start..end].starts_with("1 0 0 -1 0 100 cm"));
+    }
+
+    #[test]
+    fn transparent_shapes_are_skipped() {
+        let mut backend = PdfBackend::new(Path::new("unused.pdf"), (10, 10));
+        let before = backend.content.clone();
+
+        backend
+            .draw_rect((0, 0), (5, 5), &WHITE.mix(0.0).filled(), true)
+            .unwrap();
+        backend
+            .draw_pixel((1, 1), WHITE.mix(0.0).to_backend_color())
+            .unwrap();
+
+        assert_eq!(backend.content, before);
+        assert!(backend.opacities.is_empty());
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(pdf_string("MaxCLL (nits)"), b"(MaxCLL \\(nits\\))");
+        assert_eq!(pdf_string("a\\b"), b"(a\\\\b)");
+        assert_eq!(pdf_string("cd/m²"), b"(cd/m\xb2)");
+        assert_eq!(pdf_string("→"), b"(?)");
+    }
+}
@@ -0,0 +1,753 @@
+use ffmpeg::{format::Pixel, software::scaling, util::frame::video::Video};
+use ffmpeg_next as ffmpeg;
+use std::fmt;
+
+/// A view of one 16-bit plane of a decoded frame, excluding the row padding FFmpeg adds.
+pub struct Plane<'a> {
+    data: &'a [u16],
+    /// Distance between the starts of two rows, in samples.
+    stride: usize,
+    width: usize,
+    height: usize,
+    /// Index of this component's first sample in each row, for interleaved planes.
+    offset: usize,
+    /// Distance between two horizontally adjacent samples of this component.
+    step: usize,
+    /// Right shift that moves MSB-aligned samples (as in P010) down to their bit depth.
+    shift: u32,
+    bit_depth: u8,
+}
+
+impl<'a> Plane<'a> {
+    /// A plane of native-endian 16-bit samples, each holding `bit_depth` bits in its low bits,
+    /// with rows `stride` bytes apart. `data` must be aligned to 2 bytes, as any buffer of `u16`
+    /// is.
+    ///
+    /// ```
+    /// use measure_hdr::{Analyzer, AnalyzerOptions, pixel::{FramePlanes, Plane}};
+    ///
+    /// // A 4x2 frame of 10-bit 4:2:0, with padding at the end of each luma row
+    /// let y: Vec<u16> = vec![502, 502, 502, 502, 0, 0, 940, 940, 940, 940, 0, 0];
+    /// let (cb, cr): (Vec<u16>, Vec<u16>) = (vec![512, 512], vec![512, 512]);
+    ///
+    /// let planes = FramePlanes::ycbcr(
+    ///     Plane::new(bytemuck::cast_slice(&y), 12, 4, 2, 10)?,
+    ///     Plane::new(bytemuck::cast_slice(&cb), 4, 2, 1, 10)?,
+    ///     Plane::new(bytemuck::cast_slice(&cr), 4, 2, 1, 10)?,
+    ///     (1, 1),
+    ///     10,
+    /// )?;
+    ///
+    /// let mut analyzer = Analyzer::new(AnalyzerOptions::default());
+    /// let frame = analyzer.push_planes(0, None, &planes, None);
+    /// assert_eq!(frame.max, 1.0);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new(
+        data: &'a [u8],
+        stride: usize,
+        width: usize,
+        height: usize,
+        bit_depth: u8,
+    ) -> Result<Self, PlaneError> {
+        if !(8..=16).contains(&bit_depth) {
+            return Err(PlaneError::BitDepth(bit_depth));
+        }
+
+        let row_bytes = width * std::mem::size_of::<u16>();
+        if stride < row_bytes || !stride.is_multiple_of(2) {
+            return Err(PlaneError::Stride { stride, width });
+        }
+
+        // The last row needs no padding after it
+        let needed = match height {
+            0 => 0,
+            _ => stride * (height - 1) + row_bytes,
+        };
+        let data = data.get(..needed).ok_or(PlaneError::Length {
+            length: data.len(),
+            needed,
+        })?;
+
+        Ok(Plane {
+            data: bytemuck::try_cast_slice(data).map_err(|_| PlaneError::Misaligned)?,
+            stride: stride / std::mem::size_of::<u16>(),
+            width,
+            height,
+            offset: 0,
+            step: 1,
+            shift: 0,
+            bit_depth,
+        })
+    }
+
+    fn from_frame(frame: &'a Video, index: usize, component: &Component, bit_depth: u8) -> Self {
+        Plane {
+            data: bytemuck::cast_slice::<u8, u16>(frame.data(index)),
+            stride: frame.stride(index) / std::mem::size_of::<u16>(),
+            width: frame.plane_width(index) as usize,
+            height: frame.plane_height(index) as usize,
+            offset: component.offset,
+            step: component.step,
+            shift: component.shift,
+            bit_depth,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Iterates over the `width` samples of one row of the plane.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = u16> + use<'a> {
+        let shift = self.shift;
+
+        self.data[row * self.stride + self.offset..]
+            .iter()
+            .step_by(self.step)
+            .take(self.width)
+            .map(move |&sample| sample >> shift)
+    }
+}
+
+/// The planes of a frame in one of the layouts that can be measured natively, checked to fit
+/// together.
+pub struct FramePlanes<'a> {
+    pub(crate) components: Components<'a>,
+    pub(crate) bit_depth: u8,
+}
+
+/// The colour components of a frame and the planes they are in.
+pub(crate) enum Components<'a> {
+    YCbCr {
+        y: Plane<'a>,
+        cb: Plane<'a>,
+        cr: Plane<'a>,
+        /// log2 of the horizontal and vertical chroma subsampling factors.
+        chroma_shift: (u8, u8),
+    },
+    Gbr {
+        g: Plane<'a>,
+        b: Plane<'a>,
+        r: Plane<'a>,
+    },
+}
+
+impl<'a> FramePlanes<'a> {
+    /// The largest chroma subsampling, as log2 of the factor, in either direction.
+    const MAX_CHROMA_SHIFT: u8 = 2;
+
+    /// Y'CbCr planes, with chroma subsampled by `2^chroma_shift.0` horizontally and
+    /// `2^chroma_shift.1` vertically. Chroma planes are rounded up in size, as FFmpeg's are.
+    pub fn ycbcr(
+        y: Plane<'a>,
+        cb: Plane<'a>,
+        cr: Plane<'a>,
+        chroma_shift: (u8, u8),
+        bit_depth: u8,
+    ) -> Result<Self, FormatError> {
+        let (shift_w, shift_h) = chroma_shift;
+        if shift_w > Self::MAX_CHROMA_SHIFT || shift_h > Self::MAX_CHROMA_SHIFT {
+            return Err(FormatError::ChromaShift(chroma_shift));
+        }
+
+        let chroma = (
+            y.width.div_ceil(1 << shift_w),
+            y.height.div_ceil(1 << shift_h),
+        );
+        Self::check(&y, (y.width, y.height), bit_depth)?;
+        Self::check(&cb, chroma, bit_depth)?;
+        Self::check(&cr, chroma, bit_depth)?;
+
+        Ok(FramePlanes {
+            components: Components::YCbCr {
+                y,
+                cb,
+                cr,
+                chroma_shift,
+            },
+            bit_depth,
+        })
+    }
+
+    /// G', B' and R' planes, all the same size.
+    pub fn gbr(
+        g: Plane<'a>,
+        b: Plane<'a>,
+        r: Plane<'a>,
+        bit_depth: u8,
+    ) -> Result<Self, FormatError> {
+        let size = (g.width, g.height);
+        for plane in [&g, &b, &r] {
+            Self::check(plane, size, bit_depth)?;
+        }
+
+        Ok(FramePlanes {
+            components: Components::Gbr { g, b, r },
+            bit_depth,
+        })
+    }
+
+    /// Checks that a plane has pixels, is `size`, and holds samples of `bit_depth`.
+    fn check(plane: &Plane, size: (usize, usize), bit_depth: u8) -> Result<(), FormatError> {
+        if !(8..=16).contains(&bit_depth) {
+            return Err(FormatError::BitDepth(bit_depth));
+        }
+        if plane.bit_depth != bit_depth {
+            return Err(FormatError::PlaneBitDepth {
+                plane: plane.bit_depth,
+                frame: bit_depth,
+            });
+        }
+        if plane.width == 0 || plane.height == 0 {
+            return Err(FormatError::Empty);
+        }
+        if (plane.width, plane.height) != size {
+            return Err(FormatError::PlaneSize {
+                plane: (plane.width, plane.height),
+                expected: size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Whether the frame is R'G'B' rather than Y'CbCr.
+    pub fn is_rgb(&self) -> bool {
+        matches!(self.components, Components::Gbr { .. })
+    }
+
+    pub fn height(&self) -> usize {
+        match &self.components {
+            Components::YCbCr { y, .. } => y.height,
+            Components::Gbr { g, .. } => g.height,
+        }
+    }
+}
+
+/// Where one colour component lives within a frame.
+struct Component {
+    plane: usize,
+    offset: usize,
+    step: usize,
+    shift: u32,
+}
+
+impl Component {
+    const fn planar(plane: usize) -> Self {
+        Component {
+            plane,
+            offset: 0,
+            step: 1,
+            shift: 0,
+        }
+    }
+
+    /// A component of a semi-planar format such as P010, where samples are MSB-aligned and Cb
+    /// and Cr are interleaved in one plane.
+    const fn msb_aligned(plane: usize, offset: usize, step: usize, bit_depth: u8) -> Self {
+        Component {
+            plane,
+            offset,
+            step,
+            shift: 16 - bit_depth as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    YCbCr,
+    SemiPlanarYCbCr,
+    Gbr,
+}
+
+/// The memory layout of a pixel format that can be measured natively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    family: Family,
+    chroma_shift: (u8, u8),
+    bit_depth: u8,
+}
+
+impl Layout {
+    const fn new(family: Family, chroma_shift: (u8, u8), bit_depth: u8) -> Self {
+        Layout {
+            family,
+            chroma_shift,
+            bit_depth,
+        }
+    }
+
+    fn of(format: Pixel) -> Option<Self> {
+        use Family::*;
+
+        Some(match format {
+            Pixel::YUV420P10LE => Layout::new(YCbCr, (1, 1), 10),
+            Pixel::YUV422P10LE => Layout::new(YCbCr, (1, 0), 10),
+            Pixel::YUV444P10LE => Layout::new(YCbCr, (0, 0), 10),
+            Pixel::YUV420P12LE => Layout::new(YCbCr, (1, 1), 12),
+            Pixel::YUV422P12LE => Layout::new(YCbCr, (1, 0), 12),
+            Pixel::YUV444P12LE => Layout::new(YCbCr, (0, 0), 12),
+            Pixel::YUV420P16LE => Layout::new(YCbCr, (1, 1), 16),
+            Pixel::YUV422P16LE => Layout::new(YCbCr, (1, 0), 16),
+            Pixel::YUV444P16LE => Layout::new(YCbCr, (0, 0), 16),
+            Pixel::P010LE => Layout::new(SemiPlanarYCbCr, (1, 1), 10),
+            Pixel::P012LE => Layout::new(SemiPlanarYCbCr, (1, 1), 12),
+            Pixel::P016LE => Layout::new(SemiPlanarYCbCr, (1, 1), 16),
+            Pixel::P210LE => Layout::new(SemiPlanarYCbCr, (1, 0), 10),
+            Pixel::P410LE => Layout::new(SemiPlanarYCbCr, (0, 0), 10),
+            Pixel::GBRP10LE => Layout::new(Gbr, (0, 0), 10),
+            Pixel::GBRP12LE => Layout::new(Gbr, (0, 0), 12),
+            Pixel::GBRP16LE => Layout::new(Gbr, (0, 0), 16),
+            _ => return None,
+        })
+    }
+
+    fn planes<'a>(&self, frame: &'a Video) -> Result<FramePlanes<'a>, FormatError> {
+        let bit_depth = self.bit_depth;
+        let plane = |c: Component| Plane::from_frame(frame, c.plane, &c, bit_depth);
+
+        match self.family {
+            Family::YCbCr => FramePlanes::ycbcr(
+                plane(Component::planar(0)),
+                plane(Component::planar(1)),
+                plane(Component::planar(2)),
+                self.chroma_shift,
+                bit_depth,
+            ),
+            Family::SemiPlanarYCbCr => FramePlanes::ycbcr(
+                plane(Component::msb_aligned(0, 0, 1, bit_depth)),
+                plane(Component::msb_aligned(1, 0, 2, bit_depth)),
+                plane(Component::msb_aligned(1, 1, 2, bit_depth)),
+                self.chroma_shift,
+                bit_depth,
+            ),
+            // FFmpeg orders the planes of GBR formats G, B, R
+            Family::Gbr => FramePlanes::gbr(
+                plane(Component::planar(0)),
+                plane(Component::planar(1)),
+                plane(Component::planar(2)),
+                bit_depth,
+            ),
+        }
+    }
+}
+
+/// Picks the format that a pixel format without a native reader is converted into, or `None` if
+/// it cannot be measured at all.
+///
+/// Big-endian formats are converted as their little-endian equivalents would be read.
+/// Full-range ("JPEG") Y'CbCr formats are converted to R'G'B', as swscale would otherwise
+/// squeeze them into limited range while the frames are still tagged full range. The
+/// conversion uses the BT.601 matrix JPEG defines.
+fn canonical_format(format: Pixel) -> Option<Pixel> {
+    match format {
+        Pixel::YUV420P
+        | Pixel::YUV422P
+        | Pixel::YUV444P
+        | Pixel::NV12
+        | Pixel::NV21
+        | Pixel::Y210LE
+        | Pixel::YUV420P10BE
+        | Pixel::YUV422P10BE
+        | Pixel::YUV444P10BE
+        | Pixel::YUV420P12BE
+        | Pixel::YUV422P12BE
+        | Pixel::YUV444P12BE
+        | Pixel::YUV420P16BE
+        | Pixel::YUV422P16BE
+        | Pixel::YUV444P16BE
+        | Pixel::P010BE
+        | Pixel::P012BE
+        | Pixel::P016BE
+        | Pixel::P210BE
+        | Pixel::P410BE => Some(Pixel::YUV444P16LE),
+        Pixel::GBRP
+        | Pixel::RGB24
+        | Pixel::BGR24
+        | Pixel::RGB48LE
+        | Pixel::GBRP10BE
+        | Pixel::GBRP12BE
+        | Pixel::GBRP16BE
+        | Pixel::RGB48BE
+        | Pixel::YUVJ420P
+        | Pixel::YUVJ422P
+        | Pixel::YUVJ444P => Some(Pixel::GBRP16LE),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    Unsupported(Pixel),
+    Ffmpeg(ffmpeg::Error),
+    /// Bit depths outside 8 to 16 bits can't be held in, or don't need, 16-bit samples.
+    BitDepth(u8),
+    /// Chroma subsampled by more than 4 in either direction.
+    ChromaShift((u8, u8)),
+    /// A plane was made for a different bit depth than the frame's.
+    PlaneBitDepth {
+        plane: u8,
+        frame: u8,
+    },
+    /// A plane is not the width and height the others and the subsampling call for.
+    PlaneSize {
+        plane: (usize, usize),
+        expected: (usize, usize),
+    },
+    /// A plane has no pixels.
+    Empty,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Unsupported(format) => write!(f, "Unsupported pixel format: {format:?}"),
+            FormatError::Ffmpeg(e) => write!(f, "Pixel format conversion failed: {e}"),
+            FormatError::BitDepth(bit_depth) => write!(f, "Unsupported bit depth: {bit_depth}"),
+            FormatError::ChromaShift((w, h)) => write!(
+                f,
+                "Unsupported chroma subsampling by 2^{w} horizontally and 2^{h} vertically"
+            ),
+            FormatError::PlaneBitDepth { plane, frame } => write!(
+                f,
+                "A plane of {plane}-bit samples is in a frame of {frame}-bit samples"
+            ),
+            FormatError::PlaneSize { plane, expected } => write!(
+                f,
+                "A plane is {}x{}, but should be {}x{}",
+                plane.0, plane.1, expected.0, expected.1
+            ),
+            FormatError::Empty => write!(f, "A plane has no pixels"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<ffmpeg::Error> for FormatError {
+    fn from(e: ffmpeg::Error) -> Self {
+        FormatError::Ffmpeg(e)
+    }
+}
+
+/// Why `Plane::new` cannot make a plane of some data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaneError {
+    /// Bit depths outside 8 to 16 bits can't be held in, or don't need, 16-bit samples.
+    BitDepth(u8),
+    /// The stride is odd or shorter than a row, in bytes.
+    Stride { stride: usize, width: usize },
+    /// There are fewer bytes of data than the rows need.
+    Length { length: usize, needed: usize },
+    /// The data is not aligned to 2 bytes.
+    Misaligned,
+}
+
+impl fmt::Display for PlaneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaneError::BitDepth(bit_depth) => write!(f, "Unsupported bit depth: {bit_depth}"),
+            PlaneError::Stride { stride, width } => write!(
+                f,
+                "Invalid stride of {stride} bytes for rows of {width} 16-bit samples"
+            ),
+            PlaneError::Length { length, needed } => write!(
+                f,
+                "Plane data is {length} bytes, but its rows need {needed}"
+            ),
+            PlaneError::Misaligned => write!(f, "Plane data is not aligned to 16-bit samples"),
+        }
+    }
+}
+
+impl std::error::Error for PlaneError {}
+
+/// Converts frames that cannot be read natively into a canonical format via swscale.
+struct Converter {
+    scaler: scaling::Context,
+    output: Video,
+    input: (Pixel, u32, u32),
+}
+
+/// Hands out the planes of decoded frames, converting them first if their pixel format has no
+/// native reader.
+#[derive(Default)]
+pub struct FrameReader {
+    converter: Option<Converter>,
+}
+
+impl FrameReader {
+    /// Checks that frames in `format` can be measured, without reading any.
+    pub fn check_format(format: Pixel) -> Result<(), FormatError> {
+        if Layout::of(format).is_some() || canonical_format(format).is_some() {
+            Ok(())
+        } else {
+            Err(FormatError::Unsupported(format))
+        }
+    }
+
+    pub fn planes<'a>(&'a mut self, frame: &'a Video) -> Result<FramePlanes<'a>, FormatError> {
+        let format = frame.format();
+        if let Some(layout) = Layout::of(format) {
+            return layout.planes(frame);
+        }
+
+        let canonical = canonical_format(format).ok_or(FormatError::Unsupported(format))?;
+        let input = (format, frame.width(), frame.height());
+
+        if self.converter.as_ref().is_none_or(|c| c.input != input) {
+            let scaler = scaling::Context::get(
+                format,
+                frame.width(),
+                frame.height(),
+                canonical,
+                frame.width(),
+                frame.height(),
+                scaling::Flags::POINT,
+            )?;
+
+            self.converter = Some(Converter {
+                scaler,
+                output: Video::empty(),
+                input,
+            });
+        }
+
+        let converter = self.converter.as_mut().unwrap();
+        converter.scaler.run(frame, &mut converter.output)?;
+
+        let layout = Layout::of(canonical).expect("canonical formats are read natively");
+        layout.planes(&converter.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_rows() {
+        let samples: Vec<u16> = vec![1, 2, 3, 0, 4, 5, 6];
+        let plane = Plane::new(bytemuck::cast_slice(&samples), 8, 3, 2, 10).unwrap();
+
+        assert_eq!(plane.height(), 2);
+        assert_eq!(plane.row(0).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(plane.row(1).collect::<Vec<_>>(), [4, 5, 6]);
+    }
+
+    #[test]
+    fn invalid_planes() {
+        let samples: Vec<u16> = vec![0; 8];
+        let data: &[u8] = bytemuck::cast_slice(&samples);
+
+        assert_eq!(
+            Plane::new(data, 8, 4, 2, 7).err(),
+            Some(PlaneError::BitDepth(7))
+        );
+        assert_eq!(
+            Plane::new(data, 6, 4, 2, 10).err(),
+            Some(PlaneError::Stride {
+                stride: 6,
+                width: 4
+            })
+        );
+        assert_eq!(
+            Plane::new(data, 9, 4, 1, 10).err(),
+            Some(PlaneError::Stride {
+                stride: 9,
+                width: 4
+            })
+        );
+        assert_eq!(
+            Plane::new(data, 10, 4, 2, 10).err(),
+            Some(PlaneError::Length {
+                length: 16,
+                needed: 18
+            })
+        );
+        assert_eq!(
+            Plane::new(&data[1..], 4, 2, 1, 10).err(),
+            Some(PlaneError::Misaligned)
+        );
+        assert!(Plane::new(data, 10, 4, 0, 10).is_ok());
+    }
+
+    /// A plane of the first `width` by `height` of `samples`, with unpadded rows.
+    fn plane(samples: &[u16], width: usize, height: usize, bit_depth: u8) -> Plane<'_> {
+        let data = bytemuck::cast_slice(&samples[..width * height]);
+        Plane::new(data, width * 2, width, height, bit_depth).unwrap()
+    }
+
+    #[test]
+    fn native_layouts() {
+        use Family::*;
+
+        let layouts = [
+            (Pixel::YUV420P10LE, Layout::new(YCbCr, (1, 1), 10)),
+            (Pixel::YUV422P12LE, Layout::new(YCbCr, (1, 0), 12)),
+            (Pixel::YUV444P16LE, Layout::new(YCbCr, (0, 0), 16)),
+            (Pixel::P010LE, Layout::new(SemiPlanarYCbCr, (1, 1), 10)),
+            (Pixel::P410LE, Layout::new(SemiPlanarYCbCr, (0, 0), 10)),
+            (Pixel::GBRP10LE, Layout::new(Gbr, (0, 0), 10)),
+        ];
+
+        for (format, layout) in layouts {
+            assert_eq!(Layout::of(format), Some(layout), "{format:?}");
+            assert_eq!(canonical_format(format), None, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn converted_formats() {
+        let formats = [
+            (Pixel::YUV420P, Pixel::YUV444P16LE),
+            (Pixel::NV12, Pixel::YUV444P16LE),
+            (Pixel::YUV420P10BE, Pixel::YUV444P16LE),
+            (Pixel::YUV422P10BE, Pixel::YUV444P16LE),
+            (Pixel::YUV444P10BE, Pixel::YUV444P16LE),
+            (Pixel::YUV420P12BE, Pixel::YUV444P16LE),
+            (Pixel::P010BE, Pixel::YUV444P16LE),
+            (Pixel::YUVJ420P, Pixel::GBRP16LE),
+            (Pixel::YUVJ444P, Pixel::GBRP16LE),
+            (Pixel::RGB24, Pixel::GBRP16LE),
+            (Pixel::GBRP10BE, Pixel::GBRP16LE),
+        ];
+
+        for (format, canonical) in formats {
+            assert_eq!(Layout::of(format), None, "{format:?}");
+            assert_eq!(canonical_format(format), Some(canonical), "{format:?}");
+            assert!(Layout::of(canonical).is_some());
+            assert_eq!(FrameReader::check_format(format), Ok(()));
+        }
+
+        assert_eq!(
+            FrameReader::check_format(Pixel::GRAY8),
+            Err(FormatError::Unsupported(Pixel::GRAY8))
+        );
+    }
+
+    #[test]
+    fn msb_aligned_samples() {
+        // One row of P010 chroma: Cb and Cr interleaved, each in the top 10 bits
+        let samples: [u16; 4] = [64 << 6, 960 << 6, 512 << 6, 513 << 6];
+        let plane = |component: Component| Plane {
+            data: &samples,
+            stride: 4,
+            width: 2,
+            height: 1,
+            offset: component.offset,
+            step: component.step,
+            shift: component.shift,
+            bit_depth: 10,
+        };
+
+        let cb = plane(Component::msb_aligned(1, 0, 2, 10));
+        let cr = plane(Component::msb_aligned(1, 1, 2, 10));
+        assert_eq!(cb.row(0).collect::<Vec<_>>(), [64, 512]);
+        assert_eq!(cr.row(0).collect::<Vec<_>>(), [960, 513]);
+    }
+
+    #[test]
+    fn frame_planes() {
+        let samples = [0; 16];
+
+        // Chroma of odd-sized frames is rounded up
+        let planes = FramePlanes::ycbcr(
+            plane(&samples, 3, 3, 10),
+            plane(&samples, 2, 2, 10),
+            plane(&samples, 2, 2, 10),
+            (1, 1),
+            10,
+        )
+        .unwrap();
+        assert!(!planes.is_rgb());
+        assert_eq!(planes.height(), 3);
+
+        let planes = FramePlanes::ycbcr(
+            plane(&samples, 4, 2, 12),
+            plane(&samples, 2, 2, 12),
+            plane(&samples, 2, 2, 12),
+            (1, 0),
+            12,
+        )
+        .unwrap();
+        assert_eq!(planes.height(), 2);
+
+        let planes = FramePlanes::gbr(
+            plane(&samples, 4, 4, 16),
+            plane(&samples, 4, 4, 16),
+            plane(&samples, 4, 4, 16),
+            16,
+        )
+        .unwrap();
+        assert!(planes.is_rgb());
+    }
+
+    #[test]
+    fn mismatched_planes() {
+        let samples = [0; 16];
+        let ycbcr = |luma: (usize, usize), chroma: (usize, usize), shift, bit_depth| {
+            FramePlanes::ycbcr(
+                plane(&samples, luma.0, luma.1, 10),
+                plane(&samples, chroma.0, chroma.1, 10),
+                plane(&samples, chroma.0, chroma.1, 10),
+                shift,
+                bit_depth,
+            )
+            .err()
+        };
+
+        // Chroma smaller than subsampling calls for
+        assert_eq!(
+            ycbcr((4, 4), (2, 2), (0, 0), 10),
+            Some(FormatError::PlaneSize {
+                plane: (2, 2),
+                expected: (4, 4)
+            })
+        );
+        assert_eq!(
+            ycbcr((4, 4), (2, 1), (1, 1), 10),
+            Some(FormatError::PlaneSize {
+                plane: (2, 1),
+                expected: (2, 2)
+            })
+        );
+        assert_eq!(
+            ycbcr((4, 4), (1, 1), (3, 3), 10),
+            Some(FormatError::ChromaShift((3, 3)))
+        );
+
+        // The bit depth is checked before anything it could underflow
+        assert_eq!(
+            ycbcr((4, 4), (2, 2), (1, 1), 4),
+            Some(FormatError::BitDepth(4))
+        );
+        assert_eq!(
+            ycbcr((4, 4), (2, 2), (1, 1), 12),
+            Some(FormatError::PlaneBitDepth {
+                plane: 10,
+                frame: 12
+            })
+        );
+
+        // Without pixels there is nothing to average
+        assert_eq!(ycbcr((0, 4), (0, 2), (1, 1), 10), Some(FormatError::Empty));
+        assert_eq!(ycbcr((4, 0), (2, 0), (1, 1), 10), Some(FormatError::Empty));
+
+        let gbr = FramePlanes::gbr(
+            plane(&samples, 4, 4, 10),
+            plane(&samples, 4, 3, 10),
+            plane(&samples, 4, 4, 10),
+            10,
+        );
+        assert_eq!(
+            gbr.err(),
+            Some(FormatError::PlaneSize {
+                plane: (4, 3),
+                expected: (4, 4)
+            })
+        );
+    }
+}
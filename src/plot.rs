@@ -0,0 +1,496 @@
+use float_ord::FloatOrd;
+use plotters::{
+    coord::{
+        Shift,
+        ranged1d::{KeyPointHint, NoDefaultFormatting, ValueFormatter},
+    },
+    prelude::*,
+};
+use std::{error::Error, ops::Range, path::Path, str::FromStr};
+
+use crate::{
+    FrameInfo, Summary, export,
+    histogram::{PERCENTILES, percentile_name},
+    metadata::StaticMetadata,
+    nits_to_pq,
+    pdf::PdfBackend,
+    pq_to_nits,
+};
+
+const MAX_COLOUR: RGBColor = RGBColor(65, 105, 225);
+const AVERAGE_COLOUR: RGBColor = RGBColor(75, 0, 130);
+const MIN_COLOUR: RGBColor = BLACK;
+const PERCENTILE_COLOUR: RGBColor = RGBColor(220, 20, 60);
+
+/// The colours of overlaid inputs, in order, repeating if there are more inputs.
+const SERIES_COLOURS: [RGBColor; 6] = [
+    MAX_COLOUR,
+    RGBColor(139, 69, 19),
+    RGBColor(34, 139, 34),
+    RGBColor(255, 140, 0),
+    AVERAGE_COLOUR,
+    RGBColor(0, 128, 128),
+];
+
+/// The luminances marked on PQ-scaled axes, in nits.
+pub const PQ_AXIS_NITS: [f64; 17] = [
+    0.01, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 200.0, 400.0, 600.0, 1000.0, 2000.0,
+    4000.0, 10000.0,
+];
+
+pub struct PqCoord {}
+
+impl Ranged for PqCoord {
+    type FormatOption = NoDefaultFormatting;
+    type ValueType = f64;
+
+    fn map(&self, value: &f64, limit: (i32, i32)) -> i32 {
+        let size = limit.1 - limit.0;
+        (*value * size as f64) as i32 + limit.0
+    }
+
+    fn key_points<Hint: KeyPointHint>(&self, _hint: Hint) -> Vec<f64> {
+        PQ_AXIS_NITS.iter().map(|&nits| nits_to_pq(nits)).collect()
+    }
+
+    fn range(&self) -> Range<f64> {
+        0_f64..10000.0_f64
+    }
+}
+impl ValueFormatter<f64> for PqCoord {
+    fn format_ext(&self, value: &f64) -> String {
+        let nits = (pq_to_nits(*value) * 1000.0).round() / 1000.0;
+        format!("{nits}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Svg,
+    Pdf,
+}
+
+impl PlotFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(PlotFormat::Png),
+            "jpg" | "jpeg" => Some(PlotFormat::Jpeg),
+            "bmp" => Some(PlotFormat::Bmp),
+            "svg" => Some(PlotFormat::Svg),
+            "pdf" => Some(PlotFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PlotFormat::Png => "png",
+            PlotFormat::Jpeg => "jpg",
+            PlotFormat::Bmp => "bmp",
+            PlotFormat::Svg => "svg",
+            PlotFormat::Pdf => "pdf",
+        }
+    }
+}
+
+impl FromStr for PlotFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        PlotFormat::from_extension(s).ok_or(())
+    }
+}
+
+/// One input's measurements, as drawn in the plot.
+pub struct Series<'a> {
+    /// What the input is called in the legend when several are overlaid.
+    pub label: &'a str,
+    pub results: &'a [FrameInfo],
+    pub summary: &'a Summary,
+    pub metadata: &'a StaticMetadata,
+    /// Frames to move the measurements along the x-axis by, to line them up with the others.
+    pub offset: i64,
+}
+
+/// What to draw in the plot besides the measurements, and where.
+pub struct PlotOptions<'a> {
+    pub output: &'a Path,
+    pub format: PlotFormat,
+    pub title: &'a str,
+    pub size: (u32, u32),
+    /// Shots of the first series to shade alternately, if any.
+    pub shaded_shots: &'a [Range<usize>],
+    /// Index into `PERCENTILES` of a percentile to draw, if any.
+    pub percentile: Option<usize>,
+    /// The frame rate to show timecode on the x-axis at, rather than frame numbers.
+    pub timecode_fps: Option<f64>,
+}
+
+/// Draws the plot with the backend for its format. A single series is drawn in full, several
+/// are overlaid with a colour each. Nothing is written unless every series has frames to draw.
+pub fn plot(series: &[Series], options: &PlotOptions) -> Result<(), Box<dyn Error>> {
+    let (output, size) = (options.output, options.size);
+
+    if series.is_empty() {
+        return Err("nothing to plot".into());
+    }
+    if let Some(empty) = series.iter().find(|x| x.results.is_empty()) {
+        return Err(format!("no frames of {} to plot", empty.label).into());
+    }
+
+    match options.format {
+        PlotFormat::Png | PlotFormat::Jpeg | PlotFormat::Bmp => {
+            let root = BitMapBackend::new(output, size).into_drawing_area();
+            draw_plot(root, series, options)
+        }
+        PlotFormat::Svg => {
+            let root = SVGBackend::new(output, size).into_drawing_area();
+            draw_plot(root, series, options)
+        }
+        PlotFormat::Pdf => {
+            let root = PdfBackend::new(output, size).into_drawing_area();
+            draw_plot(root, series, options)
+        }
+    }
+}
+
+/// A short line in the colour of a series, for its legend entry.
+fn legend_line<C: Color + Copy>(
+    colour: C,
+    stroke_width: u32,
+) -> impl Fn((i32, i32)) -> PathElement<(i32, i32)> {
+    move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], colour.stroke_width(stroke_width))
+}
+
+fn draw_plot<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    series: &[Series],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let root = root
+        .margin(30, 30, 60, 60)
+        .titled(options.title, ("sans-serif", 40))?;
+
+    // With a timecode axis, frames are placed by their presentation time in seconds
+    let x_of = |series: &Series, x: &FrameInfo| match options.timecode_fps {
+        Some(fps) => x.time.unwrap_or(x.frame as f64 / fps) + series.offset as f64 / fps,
+        None => (x.frame as i64 + series.offset) as f64,
+    };
+    // Frames measured without percentiles have none to draw
+    let percentile_line = |series: &Series, i: usize| {
+        let results = series.results.iter();
+        results
+            .filter_map(|x| Some((x_of(series, x), x.percentiles?[i])))
+            .collect::<Vec<_>>()
+    };
+    let frame_width = options.timecode_fps.map_or(1.0, |fps| 1.0 / fps);
+    let x_start = series
+        .iter()
+        .filter_map(|s| Some(FloatOrd(x_of(s, s.results.first()?))))
+        .min()
+        .ok_or("nothing to plot")?
+        .0;
+    let x_end = series
+        .iter()
+        .filter_map(|s| Some(FloatOrd(x_of(s, s.results.last()?))))
+        .max()
+        .ok_or("nothing to plot")?
+        .0;
+    let x_spec = x_start..x_end + frame_width;
+
+    let x_label_formatter = |x: &f64| match options.timecode_fps {
+        Some(fps) => export::format_timecode(*x, fps),
+        None => format!("{x:.0}"),
+    };
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(60)
+        .y_label_area_size(60)
+        .margin_top(90)
+        .build_cartesian_2d(x_spec.clone(), PqCoord {})?;
+
+    chart
+        .configure_mesh()
+        .bold_line_style(BLACK.mix(0.10))
+        .light_line_style(BLACK.mix(0.01))
+        .label_style(("sans-serif", 22))
+        .axis_desc_style(("sans-serif", 24))
+        .x_desc(if options.timecode_fps.is_some() {
+            "timecode"
+        } else {
+            "frames"
+        })
+        .x_label_formatter(&x_label_formatter)
+        .x_max_light_lines(1)
+        // Timecodes are about twice as wide as frame numbers
+        .x_labels(if options.timecode_fps.is_some() {
+            12
+        } else {
+            24
+        })
+        .y_desc("nits (cd/m²)")
+        .draw()?;
+
+    // Every other shot is shaded, so that the first is left clear
+    let first = &series[0];
+    chart.draw_series(options.shaded_shots.iter().skip(1).step_by(2).map(|shot| {
+        let start = x_of(first, &first.results[shot.start]);
+        let end = x_of(first, &first.results[shot.end - 1]) + frame_width;
+        Rectangle::new([(start, 0.0), (end, 1.0)], BLACK.mix(0.06).filled())
+    }))?;
+
+    if let [series] = series {
+        let results = series.results;
+        let summary = series.summary;
+
+        let avg_series_label = format!(
+            "Average (MaxFALL: {:.2} nits, avg: {:.2} nits)",
+            summary.max_fall, summary.avg_fall
+        );
+
+        let max_series_label = if summary.robust_max_cll < summary.max_cll {
+            format!(
+                "Maximum (MaxCLL: {:.2} nits, ignoring hot pixels: {:.2} nits, avg: {:.2} nits)",
+                summary.max_cll, summary.robust_max_cll, summary.avg_max,
+            )
+        } else {
+            format!(
+                "Maximum (MaxCLL: {:.2} nits, avg: {:.2} nits)",
+                summary.max_cll, summary.avg_max,
+            )
+        };
+
+        let min_series_label = format!("Minimum (max: {:.6} nits)", summary.max_min);
+
+        let max_series = AreaSeries::new(
+            results.iter().map(|x| (x_of(series, x), x.max)),
+            0.0,
+            MAX_COLOUR.mix(0.25),
+        )
+        .border_style(MAX_COLOUR);
+        let avg_series = AreaSeries::new(
+            results.iter().map(|x| (x_of(series, x), x.avg)),
+            0.0,
+            AVERAGE_COLOUR.mix(0.25),
+        )
+        .border_style(AVERAGE_COLOUR);
+        let min_series = AreaSeries::new(
+            results.iter().map(|x| (x_of(series, x), x.min)),
+            0.0,
+            BLACK.mix(0.25),
+        )
+        .border_style(BLACK);
+
+        chart
+            .draw_series(max_series)?
+            .label(max_series_label)
+            .legend(legend_line(MAX_COLOUR, 2));
+
+        chart
+            .draw_series(avg_series)?
+            .label(avg_series_label)
+            .legend(legend_line(AVERAGE_COLOUR, 2));
+
+        chart
+            .draw_series(min_series)?
+            .label(min_series_label)
+            .legend(legend_line(MIN_COLOUR, 2));
+
+        let percentile_max = options.percentile.and_then(|i| {
+            let max = results
+                .iter()
+                .filter_map(|x| Some(FloatOrd(x.percentiles?[i])));
+            Some((i, max.max()?.0))
+        });
+
+        if let Some((i, max)) = percentile_max {
+            let name = percentile_name(PERCENTILES[i]);
+
+            chart
+                .draw_series(LineSeries::new(
+                    percentile_line(series, i),
+                    PERCENTILE_COLOUR.stroke_width(2),
+                ))?
+                .label(format!("{name} (max: {:.2} nits)", pq_to_nits(max)))
+                .legend(legend_line(PERCENTILE_COLOUR, 2));
+        }
+
+        if let Some(cll) = series.metadata.content_light_level {
+            let declared = [
+                (cll.max_cll, "Declared MaxCLL", MAX_COLOUR),
+                (cll.max_fall, "Declared MaxFALL", AVERAGE_COLOUR),
+            ];
+
+            // CTA-861.3 defines 0 as unknown, so there is no level to draw
+            for (nits, label, colour) in declared.into_iter().filter(|x| x.0 != 0) {
+                let pq = nits_to_pq(nits as f64);
+                let line = [(x_spec.start, pq), (x_spec.end, pq)];
+
+                chart
+                    .draw_series(DashedLineSeries::new(line, 12, 8, colour.stroke_width(2)))?
+                    .label(format!("{label}: {nits} nits"))
+                    .legend(legend_line(colour, 2));
+            }
+        }
+    } else {
+        // Overlaid inputs are drawn as lines, as filled areas would hide each other. Each input
+        // has a colour, with its maxima drawn thicker than its averages and minima.
+        for (series, colour) in series.iter().zip(SERIES_COLOURS.iter().cycle()) {
+            let (label, results, summary) = (series.label, series.results, series.summary);
+
+            chart
+                .draw_series(LineSeries::new(
+                    results.iter().map(|x| (x_of(series, x), x.max)),
+                    colour.stroke_width(2),
+                ))?
+                .label(format!(
+                    "{label}: Maximum (MaxCLL: {:.2} nits, avg: {:.2} nits)",
+                    summary.max_cll, summary.avg_max
+                ))
+                .legend(legend_line(*colour, 2));
+
+            chart
+                .draw_series(LineSeries::new(
+                    results.iter().map(|x| (x_of(series, x), x.avg)),
+                    colour.mix(0.6).stroke_width(1),
+                ))?
+                .label(format!(
+                    "{label}: Average (MaxFALL: {:.2} nits, avg: {:.2} nits)",
+                    summary.max_fall, summary.avg_fall
+                ))
+                .legend(legend_line(colour.mix(0.6), 1));
+
+            chart
+                .draw_series(LineSeries::new(
+                    results.iter().map(|x| (x_of(series, x), x.min)),
+                    colour.mix(0.3).stroke_width(1),
+                ))?
+                .label(format!(
+                    "{label}: Minimum (max: {:.6} nits)",
+                    summary.max_min
+                ))
+                .legend(legend_line(colour.mix(0.3), 1));
+
+            if let Some(i) = options.percentile {
+                let name = percentile_name(PERCENTILES[i]);
+                let line = percentile_line(series, i);
+
+                chart
+                    .draw_series(DashedLineSeries::new(line, 8, 6, colour.stroke_width(1)))?
+                    .label(format!("{label}: {name}"))
+                    .legend(legend_line(*colour, 1));
+            }
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .border_style(MIN_COLOUR)
+        .position(SeriesLabelPosition::LowerLeft)
+        .label_font(("sans-serif", 24))
+        .background_style(WHITE)
+        .draw()?;
+
+    let chart_caption = match series {
+        [series] => format!("Frames: {}", series.results.len()),
+        _ => {
+            let frames = series
+                .iter()
+                .map(|x| format!("{} {}", x.label, x.results.len()))
+                .collect::<Vec<_>>();
+            format!("Frames: {}", frames.join(", "))
+        }
+    };
+
+    let caption_style = ("sans-serif", 24).into_text_style(&root);
+    root.draw_text(&chart_caption, &caption_style, (60, 10))?;
+    root.present()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ContentLightLevel;
+
+    fn options(output: &Path) -> PlotOptions<'_> {
+        PlotOptions {
+            output,
+            format: PlotFormat::Svg,
+            title: "",
+            size: (800, 600),
+            shaded_shots: &[],
+            percentile: None,
+            timecode_fps: None,
+        }
+    }
+
+    #[test]
+    fn nothing_to_plot() {
+        let output = std::env::temp_dir().join("measure-hdr-nothing-to-plot.svg");
+        let results = [FrameInfo::uniform(0, 0.5)];
+        let summary = Summary::new(&results).unwrap();
+        let metadata = StaticMetadata::default();
+        let series = |label, results| Series {
+            label,
+            results,
+            summary: &summary,
+            metadata: &metadata,
+            offset: 0,
+        };
+
+        assert!(plot(&[], &options(&output)).is_err());
+
+        let error = plot(
+            &[series("a", &results), series("b", &[])],
+            &options(&output),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "no frames of b to plot");
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn unknown_declared_levels_are_not_drawn() {
+        let output = std::env::temp_dir().join("measure-hdr-unknown-declared-levels.svg");
+        let results = [FrameInfo::uniform(0, 0.5), FrameInfo::uniform(1, 0.6)];
+        let summary = Summary::new(&results).unwrap();
+        let metadata = StaticMetadata {
+            content_light_level: Some(ContentLightLevel {
+                max_cll: 0,
+                max_fall: 400,
+            }),
+            mastering_display: None,
+        };
+        let series = Series {
+            label: "",
+            results: &results,
+            summary: &summary,
+            metadata: &metadata,
+            offset: 0,
+        };
+
+        plot(&[series], &options(&output)).unwrap();
+        let svg = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        assert!(svg.contains("Declared MaxFALL: 400 nits"));
+        assert!(!svg.contains("Declared MaxCLL"));
+    }
+
+    #[test]
+    fn series_colours_are_distinct() {
+        for (i, colour) in SERIES_COLOURS.iter().enumerate() {
+            assert_ne!(*colour, PERCENTILE_COLOUR);
+            assert!(!SERIES_COLOURS[i + 1..].contains(colour));
+        }
+    }
+}
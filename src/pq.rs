@@ -0,0 +1,50 @@
+use std::sync::LazyLock;
+
+// Contants from the SMPTE 2084 PQ spec
+pub const ST2084_Y_MAX: f64 = 10000.0;
+pub const ST2084_M1: f64 = 2610.0 / 16384.0;
+pub const ST2084_M2: f64 = (2523.0 / 4096.0) * 128.0;
+pub const ST2084_C1: f64 = 3424.0 / 4096.0;
+pub const ST2084_C2: f64 = (2413.0 / 4096.0) * 32.0;
+pub const ST2084_C3: f64 = (2392.0 / 4096.0) * 32.0;
+
+/// Converts a PQ signal value to linear light, in nits.
+pub fn pq_to_nits(pq: f64) -> f64 {
+    let pq = pq.clamp(0.0, 1.0);
+
+    // Inverse EOTF for PQ
+    let v_p = pq.powf(1.0 / ST2084_M2);
+    let n = ((v_p - ST2084_C1).max(0.0) / (ST2084_C2 - ST2084_C3 * v_p)).powf(1.0 / ST2084_M1);
+
+    n * ST2084_Y_MAX
+}
+
+/// Converts a 10-bit PQ code value to a PQ signal value.
+pub fn yuv420_10bit_to_pq(sample: u16) -> f64 {
+    const YUV420_10BIT_MAX: f64 = 1023.0;
+    let pq_code_value = (sample as f64).clamp(0.0, YUV420_10BIT_MAX);
+
+    pq_code_value / 1023.0
+}
+
+/// Linear light, in nits, of every 10-bit PQ code value.
+pub static PQ_10BIT_TO_NITS: LazyLock<[f64; 1024]> =
+    LazyLock::new(|| std::array::from_fn(|code| pq_to_nits(yuv420_10bit_to_pq(code as u16))));
+
+/// Converts linear light, in nits, to a PQ signal value.
+pub fn nits_to_pq(nits: f64) -> f64 {
+    let y = nits / ST2084_Y_MAX;
+
+    ((ST2084_C1 + ST2084_C2 * y.powf(ST2084_M1)) / (1.0 + ST2084_C3 * y.powf(ST2084_M1)))
+        .powf(ST2084_M2)
+}
+
+/// The linear light, in nits, halfway in PQ signal between each 10-bit code value and the next.
+static PQ_10BIT_THRESHOLDS: LazyLock<[f64; 1023]> =
+    LazyLock::new(|| std::array::from_fn(|code| pq_to_nits((code as f64 + 0.5) / 1023.0)));
+
+/// Converts linear light, in nits, to the nearest 10-bit PQ code value. Much quicker than
+/// rounding `nits_to_pq`, which matters when converting every pixel of a frame.
+pub fn nits_to_10bit_pq(nits: f64) -> u16 {
+    PQ_10BIT_THRESHOLDS.partition_point(|&threshold| threshold <= nits) as u16
+}
@@ -0,0 +1,512 @@
+use std::io::{self, Write};
+
+use crate::{
+    FrameInfo, Summary,
+    histogram::{PERCENTILES, Percentiles, percentile_name},
+    metadata::StaticMetadata,
+    plot::PQ_AXIS_NITS,
+    pq_to_nits,
+};
+
+/// Everything shown in the HTML report.
+pub struct Report<'a> {
+    pub title: &'a str,
+    pub results: &'a [FrameInfo],
+    pub summary: &'a Summary,
+    /// Percentiles over every pixel of every frame, if they were measured.
+    pub percentiles: Option<&'a Percentiles>,
+    pub metadata: &'a StaticMetadata,
+    /// Properties of the file and stream, in the order they are listed.
+    pub properties: &'a [(&'static str, String)],
+    /// Frame rate for timecodes, if the stream has one.
+    pub fps: Option<f64>,
+}
+
+/// Escapes text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats a series of PQ signal values as a JSON array, rounded to keep the file small.
+fn json_series(results: &[FrameInfo], value: impl Fn(&FrameInfo) -> f64) -> String {
+    let values: Vec<_> = results.iter().map(|x| format!("{:.6}", value(x))).collect();
+    format!("[{}]", values.join(","))
+}
+
+impl Report<'_> {
+    /// The rows of the summary table.
+    fn summary_rows(&self) -> Vec<(String, String)> {
+        let summary = self.summary;
+        let mut rows = vec![
+            ("Frames".to_owned(), summary.frames.to_string()),
+            (
+                "MaxCLL".to_owned(),
+                format!(
+                    "{:.2} nits (frame {})",
+                    summary.max_cll, summary.max_cll_frame
+                ),
+            ),
+        ];
+
+        if summary.robust_max_cll < summary.max_cll {
+            rows.push((
+                "MaxCLL ignoring hot pixels".to_owned(),
+                format!(
+                    "{:.2} nits (frame {})",
+                    summary.robust_max_cll, summary.robust_max_cll_frame
+                ),
+            ));
+        }
+
+        rows.extend([
+            (
+                "MaxFALL".to_owned(),
+                format!(
+                    "{:.2} nits (frame {})",
+                    summary.max_fall, summary.max_fall_frame
+                ),
+            ),
+            (
+                "Average maximum".to_owned(),
+                format!("{:.2} nits", summary.avg_max),
+            ),
+            (
+                "Average FALL".to_owned(),
+                format!("{:.2} nits", summary.avg_fall),
+            ),
+            (
+                "Highest minimum".to_owned(),
+                format!("{:.6} nits", summary.max_min),
+            ),
+        ]);
+
+        for (percent, pq) in PERCENTILES
+            .iter()
+            .zip(self.percentiles.into_iter().flatten())
+        {
+            rows.push((
+                format!("{} over all pixels", percentile_name(*percent)),
+                format!("{:.2} nits", pq_to_nits(*pq)),
+            ));
+        }
+
+        rows.push((
+            "Declared MaxCLL, MaxFALL".to_owned(),
+            match self.metadata.content_light_level {
+                Some(cll) => format!("{} nits, {} nits", cll.max_cll, cll.max_fall),
+                None => "none".to_owned(),
+            },
+        ));
+        rows.push((
+            "Declared mastering display".to_owned(),
+            match self.metadata.mastering_display {
+                Some(mastering_display) => mastering_display.to_string(),
+                None => "none".to_owned(),
+            },
+        ));
+
+        for mismatch in self.metadata.mismatches(summary) {
+            rows.push(("Metadata mismatch".to_owned(), mismatch));
+        }
+
+        rows
+    }
+
+    /// Writes the report as a single HTML file, with the measurements and the script that
+    /// charts them inline.
+    pub fn write_html(&self, mut writer: impl Write) -> io::Result<()> {
+        let title = escape(self.title);
+
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, "<html lang=\"en\">")?;
+        writeln!(writer, "<head>")?;
+        writeln!(writer, "<meta charset=\"utf-8\">")?;
+        writeln!(writer, "<title>{title}</title>")?;
+        writeln!(writer, "<style>{STYLE}</style>")?;
+        writeln!(writer, "</head>")?;
+        writeln!(writer, "<body>")?;
+        writeln!(writer, "<h1>{title}</h1>")?;
+        writeln!(
+            writer,
+            "<p class=\"hint\">Scroll to zoom, drag to pan, double-click to reset.</p>"
+        )?;
+        writeln!(
+            writer,
+            "<div id=\"chart\"><canvas></canvas><div id=\"tooltip\"></div></div>"
+        )?;
+
+        for (heading, rows) in [
+            ("Summary", self.summary_rows()),
+            (
+                "File",
+                self.properties
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+            ),
+        ] {
+            writeln!(writer, "<h2>{heading}</h2>")?;
+            writeln!(writer, "<table>")?;
+            for (name, value) in rows {
+                writeln!(
+                    writer,
+                    "<tr><th>{}</th><td>{}</td></tr>",
+                    escape(&name),
+                    escape(&value)
+                )?;
+            }
+            writeln!(writer, "</table>")?;
+        }
+
+        let frames: Vec<_> = self.results.iter().map(|x| x.frame.to_string()).collect();
+        let times: Vec<_> = self
+            .results
+            .iter()
+            .map(|x| {
+                x.time
+                    .map_or_else(|| "null".to_owned(), |x| format!("{x:.6}"))
+            })
+            .collect();
+        let ticks: Vec<_> = PQ_AXIS_NITS.iter().map(|x| x.to_string()).collect();
+
+        writeln!(writer, "<script>")?;
+        writeln!(writer, "const DATA = {{")?;
+        writeln!(writer, "  frame: [{}],", frames.join(","))?;
+        writeln!(writer, "  time: [{}],", times.join(","))?;
+        writeln!(writer, "  max: {},", json_series(self.results, |x| x.max))?;
+        writeln!(writer, "  avg: {},", json_series(self.results, |x| x.avg))?;
+        writeln!(writer, "  min: {},", json_series(self.results, |x| x.min))?;
+        writeln!(
+            writer,
+            "  fps: {},",
+            self.fps
+                .map_or_else(|| "null".to_owned(), |x| x.to_string())
+        )?;
+        writeln!(writer, "  ticks: [{}],", ticks.join(","))?;
+        writeln!(writer, "}};")?;
+        writeln!(writer, "{SCRIPT}")?;
+        writeln!(writer, "</script>")?;
+        writeln!(writer, "</body>")?;
+        writeln!(writer, "</html>")?;
+
+        writer.flush()
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.6em; }
+.hint { color: #666; }
+#chart { position: relative; width: 100%; height: 520px; }
+#chart canvas { width: 100%; height: 100%; cursor: crosshair; }
+#tooltip { position: absolute; display: none; pointer-events: none; background: #fff;
+  border: 1px solid #999; padding: 0.4em 0.6em; font-size: 0.9em; white-space: nowrap; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { text-align: left; padding: 0.3em 1em 0.3em 0; border-bottom: 1px solid #ddd; }
+th { font-weight: normal; color: #555; }
+";
+
+/// Draws the chart on a PQ-scaled y-axis like the plot's, and handles zooming, panning and
+/// the tooltip.
+const SCRIPT: &str = r##"
+(() => {
+  const M1 = 2610 / 16384, M2 = 2523 / 4096 * 128;
+  const C1 = 3424 / 4096, C2 = 2413 / 4096 * 32, C3 = 2392 / 4096 * 32;
+  const pqToNits = pq => {
+    const p = Math.pow(Math.min(Math.max(pq, 0), 1), 1 / M2);
+    return 10000 * Math.pow(Math.max(p - C1, 0) / (C2 - C3 * p), 1 / M1);
+  };
+  const nitsToPq = nits => {
+    const y = Math.pow(nits / 10000, M1);
+    return Math.pow((C1 + C2 * y) / (1 + C3 * y), M2);
+  };
+
+  const pad = (n, width) => String(n).padStart(width, "0");
+  const timecode = i => {
+    const seconds = DATA.time[i] ?? (DATA.fps ? DATA.frame[i] / DATA.fps : null);
+    if (seconds === null) return "unknown";
+    const hms = s => `${pad(Math.floor(s / 3600), 2)}:${pad(Math.floor(s / 60) % 60, 2)}:${pad(s % 60, 2)}`;
+    if (DATA.fps) {
+      const nominal = Math.max(Math.round(DATA.fps), 1);
+      const frames = Math.round(seconds * DATA.fps);
+      return `${hms(Math.floor(frames / nominal))}:${pad(frames % nominal, 2)}`;
+    }
+    const millis = Math.round(seconds * 1000);
+    return `${hms(Math.floor(millis / 1000))}.${pad(millis % 1000, 3)}`;
+  };
+
+  const SERIES = [
+    { key: "max", name: "Maximum", colour: "65, 105, 225" },
+    { key: "avg", name: "Average", colour: "75, 0, 130" },
+    { key: "min", name: "Minimum", colour: "0, 0, 0" },
+  ];
+  const MARGIN = { left: 70, right: 20, top: 20, bottom: 40 };
+
+  const container = document.getElementById("chart");
+  const canvas = container.querySelector("canvas");
+  const tooltip = document.getElementById("tooltip");
+  const context = canvas.getContext("2d");
+
+  const first = DATA.frame[0], last = DATA.frame[DATA.frame.length - 1] + 1;
+  let view = [first, last];
+  let hover = null;
+
+  const plotWidth = () => canvas.clientWidth - MARGIN.left - MARGIN.right;
+  const plotHeight = () => canvas.clientHeight - MARGIN.top - MARGIN.bottom;
+  const xOf = frame => MARGIN.left + (frame - view[0]) / (view[1] - view[0]) * plotWidth();
+  const frameAt = x => view[0] + (x - MARGIN.left) / plotWidth() * (view[1] - view[0]);
+  const yOf = pq => MARGIN.top + (1 - pq) * plotHeight();
+
+  // Index of the first measured frame at or after `frame`
+  const indexAt = frame => {
+    let low = 0, high = DATA.frame.length;
+    while (low < high) {
+      const mid = (low + high) >> 1;
+      if (DATA.frame[mid] < frame) low = mid + 1; else high = mid;
+    }
+    return low;
+  };
+
+  const draw = () => {
+    const scale = window.devicePixelRatio || 1;
+    canvas.width = canvas.clientWidth * scale;
+    canvas.height = canvas.clientHeight * scale;
+    context.setTransform(scale, 0, 0, scale, 0, 0);
+    context.clearRect(0, 0, canvas.clientWidth, canvas.clientHeight);
+    context.font = "12px sans-serif";
+
+    context.strokeStyle = "rgba(0, 0, 0, 0.1)";
+    context.fillStyle = "#222";
+    context.textAlign = "right";
+    context.textBaseline = "middle";
+    for (const nits of DATA.ticks) {
+      const y = yOf(nitsToPq(nits));
+      context.beginPath();
+      context.moveTo(MARGIN.left, y);
+      context.lineTo(MARGIN.left + plotWidth(), y);
+      context.stroke();
+      context.fillText(nits, MARGIN.left - 6, y);
+    }
+
+    context.textAlign = "center";
+    context.textBaseline = "top";
+    const step = Math.max(1, Math.ceil((view[1] - view[0]) / 10));
+    for (let frame = Math.ceil(view[0] / step) * step; frame < view[1]; frame += step) {
+      context.fillText(frame, xOf(frame), MARGIN.top + plotHeight() + 6);
+    }
+
+    const start = Math.max(indexAt(view[0]) - 1, 0);
+    const end = Math.min(indexAt(view[1]) + 1, DATA.frame.length);
+
+    context.save();
+    context.beginPath();
+    context.rect(MARGIN.left, MARGIN.top, plotWidth(), plotHeight());
+    context.clip();
+    for (const series of SERIES) {
+      const values = DATA[series.key];
+      context.beginPath();
+      context.moveTo(xOf(DATA.frame[start]), yOf(0));
+      for (let i = start; i < end; i++) {
+        context.lineTo(xOf(DATA.frame[i]), yOf(values[i]));
+      }
+      context.lineTo(xOf(DATA.frame[end - 1]), yOf(0));
+      context.fillStyle = `rgba(${series.colour}, 0.25)`;
+      context.fill();
+      context.strokeStyle = `rgb(${series.colour})`;
+      context.stroke();
+    }
+
+    if (hover !== null) {
+      const x = xOf(DATA.frame[hover]);
+      context.strokeStyle = "rgba(0, 0, 0, 0.5)";
+      context.beginPath();
+      context.moveTo(x, MARGIN.top);
+      context.lineTo(x, MARGIN.top + plotHeight());
+      context.stroke();
+    }
+    context.restore();
+  };
+
+  const showTooltip = event => {
+    const x = event.offsetX;
+    const target = frameAt(x);
+    let i = Math.min(indexAt(target), DATA.frame.length - 1);
+    if (i > 0 && target - DATA.frame[i - 1] < DATA.frame[i] - target) i--;
+    hover = i;
+
+    const rows = SERIES.map(s => `${s.name}: ${pqToNits(DATA[s.key][i]).toFixed(2)} nits`);
+    tooltip.innerHTML = [`Frame ${DATA.frame[i]}`, timecode(i), ...rows].join("<br>");
+    tooltip.style.display = "block";
+    const left = x + 16 + tooltip.offsetWidth > canvas.clientWidth ? x - 16 - tooltip.offsetWidth : x + 16;
+    tooltip.style.left = `${left}px`;
+    tooltip.style.top = `${MARGIN.top}px`;
+    draw();
+  };
+
+  let dragging = null;
+
+  canvas.addEventListener("wheel", event => {
+    event.preventDefault();
+    const anchor = frameAt(event.offsetX);
+    const factor = event.deltaY > 0 ? 1.25 : 0.8;
+    const width = Math.min(Math.max((view[1] - view[0]) * factor, 10), last - first);
+    let start = anchor - (anchor - view[0]) * width / (view[1] - view[0]);
+    start = Math.min(Math.max(start, first), last - width);
+    view = [start, start + width];
+    showTooltip(event);
+  }, { passive: false });
+
+  canvas.addEventListener("mousedown", event => {
+    dragging = { x: event.offsetX, view: view.slice() };
+  });
+  window.addEventListener("mouseup", () => { dragging = null; });
+  canvas.addEventListener("mousemove", event => {
+    if (dragging) {
+      const width = dragging.view[1] - dragging.view[0];
+      let start = dragging.view[0] - (event.offsetX - dragging.x) / plotWidth() * width;
+      start = Math.min(Math.max(start, first), last - width);
+      view = [start, start + width];
+    }
+    showTooltip(event);
+  });
+  canvas.addEventListener("mouseleave", () => {
+    hover = null;
+    tooltip.style.display = "none";
+    draw();
+  });
+  canvas.addEventListener("dblclick", () => {
+    view = [first, last];
+    draw();
+  });
+  window.addEventListener("resize", draw);
+
+  draw();
+})();
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames() -> [FrameInfo; 2] {
+        [
+            FrameInfo {
+                robust_max: 0.0,
+                time: Some(0.0),
+                ..FrameInfo::uniform(0, 1.0)
+            },
+            FrameInfo::uniform(1, 0.0),
+        ]
+    }
+
+    fn html(report: &Report) -> String {
+        let mut html = Vec::new();
+        report.write_html(&mut html).unwrap();
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape(r#"<b class="x">Tom & Jerry</b>"#),
+            "&lt;b class=&quot;x&quot;&gt;Tom &amp; Jerry&lt;/b&gt;"
+        );
+
+        let results = frames();
+        let summary = Summary::new(&results).unwrap();
+        let html = html(&Report {
+            title: "<script>alert(1)</script>",
+            results: &results,
+            summary: &summary,
+            percentiles: None,
+            metadata: &StaticMetadata::default(),
+            properties: &[("File", "a & <b>.mkv".to_owned())],
+            fps: None,
+        });
+
+        assert!(html.contains("<title>&lt;script&gt;alert(1)&lt;/script&gt;</title>"));
+        assert!(html.contains("<h1>&lt;script&gt;alert(1)&lt;/script&gt;</h1>"));
+        assert!(html.contains("<tr><th>File</th><td>a &amp; &lt;b&gt;.mkv</td></tr>"));
+        assert!(!html.contains("alert(1)</script>"));
+    }
+
+    #[test]
+    fn summary_rows() {
+        let results = frames();
+        let summary = Summary::new(&results).unwrap();
+        let percentiles = [0.0; PERCENTILES.len()];
+        let report = Report {
+            title: "",
+            results: &results,
+            summary: &summary,
+            percentiles: Some(&percentiles),
+            metadata: &StaticMetadata::default(),
+            properties: &[],
+            fps: None,
+        };
+
+        let rows = report.summary_rows();
+        let rows: Vec<_> = rows.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+        assert_eq!(
+            rows,
+            [
+                ("Frames", "2"),
+                ("MaxCLL", "10000.00 nits (frame 0)"),
+                ("MaxCLL ignoring hot pixels", "0.00 nits (frame 1)"),
+                ("MaxFALL", "10000.00 nits (frame 0)"),
+                ("Average maximum", "5000.00 nits"),
+                ("Average FALL", "5000.00 nits"),
+                ("Highest minimum", "10000.000000 nits"),
+                ("Median over all pixels", "0.00 nits"),
+                ("P99 over all pixels", "0.00 nits"),
+                ("P99.9 over all pixels", "0.00 nits"),
+                ("P99.99 over all pixels", "0.00 nits"),
+                ("Declared MaxCLL, MaxFALL", "none"),
+                ("Declared mastering display", "none"),
+            ]
+        );
+
+        // Without percentiles or hot pixels, those rows are left out
+        let results = [FrameInfo::uniform(0, 0.0)];
+        let summary = Summary::new(&results).unwrap();
+        let report = Report {
+            results: &results,
+            summary: &summary,
+            percentiles: None,
+            ..report
+        };
+        let rows = report.summary_rows();
+        assert_eq!(rows.len(), 8);
+        assert!(!rows.iter().any(|(name, _)| name.contains("hot pixels")));
+        assert!(
+            !rows
+                .iter()
+                .any(|(name, _)| name.contains("over all pixels"))
+        );
+    }
+
+    #[test]
+    fn data_per_frame() {
+        let results = frames();
+        let summary = Summary::new(&results).unwrap();
+        let html = html(&Report {
+            title: "",
+            results: &results,
+            summary: &summary,
+            percentiles: None,
+            metadata: &StaticMetadata::default(),
+            properties: &[],
+            fps: Some(24.0),
+        });
+
+        assert!(html.contains("  frame: [0,1],\n"));
+        assert!(html.contains("  time: [0.000000,null],\n"));
+        assert!(html.contains("  max: [1.000000,0.000000],\n"));
+        assert!(html.contains("  avg: [1.000000,0.000000],\n"));
+        assert!(html.contains("  min: [1.000000,0.000000],\n"));
+        assert!(html.contains("  fps: 24,\n"));
+    }
+}
@@ -0,0 +1,275 @@
+use std::{io, ops::Range};
+
+use crate::{FrameInfo, Summary, frame::NotMeasured};
+
+/// The default threshold for `SceneDetector::AverageJump`, in PQ signal.
+const AVERAGE_JUMP_THRESHOLD: f64 = 0.05;
+/// The default threshold for `SceneDetector::Histogram`, as a fraction of pixels.
+const HISTOGRAM_THRESHOLD: f64 = 0.5;
+
+/// How cuts between shots are found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SceneDetector {
+    /// A cut wherever a frame's average PQ signal value differs from the previous frame's by
+    /// more than the threshold.
+    AverageJump(f64),
+    /// A cut wherever the coarse maxRGB histograms of adjacent frames differ by more than the
+    /// threshold, as the fraction of pixels that would have to move to turn one into the other.
+    Histogram(f64),
+}
+
+impl SceneDetector {
+    /// Picks a detector by name, with the method's default threshold if none is given.
+    pub fn new(method: &str, threshold: Option<f64>) -> Option<Self> {
+        match method {
+            "avg" => Some(SceneDetector::AverageJump(
+                threshold.unwrap_or(AVERAGE_JUMP_THRESHOLD),
+            )),
+            "histogram" => Some(SceneDetector::Histogram(
+                threshold.unwrap_or(HISTOGRAM_THRESHOLD),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Whether frames have to keep their `FrameInfo::profile` for this detector.
+    pub fn needs_profiles(self) -> bool {
+        matches!(self, SceneDetector::Histogram(_))
+    }
+
+    fn is_cut(self, previous: &FrameInfo, frame: &FrameInfo) -> Result<bool, NotMeasured> {
+        match self {
+            SceneDetector::AverageJump(threshold) => {
+                Ok((frame.avg_signal - previous.avg_signal).abs() > threshold)
+            }
+            SceneDetector::Histogram(threshold) => {
+                let (Some(previous), Some(profile)) = (&previous.profile, &frame.profile) else {
+                    return Err(NotMeasured("shot detection by histogram"));
+                };
+                Ok(previous.difference(profile) > threshold)
+            }
+        }
+    }
+}
+
+/// Splits measured frames into shots, returning the range of indices into `results` that each
+/// covers. Detection by histogram fails unless the frames kept their profiles.
+pub fn detect_shots(
+    results: &[FrameInfo],
+    detector: SceneDetector,
+) -> Result<Vec<Range<usize>>, NotMeasured> {
+    let mut shots = Vec::new();
+    let mut start = 0;
+
+    for (i, pair) in results.windows(2).enumerate() {
+        if detector.is_cut(&pair[0], &pair[1])? {
+            shots.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+
+    if start < results.len() {
+        shots.push(start..results.len());
+    }
+
+    Ok(shots)
+}
+
+/// The frames of `results` in `shot`, for the writers of per-shot metadata. A shot that is empty
+/// or runs past the measured frames is an `InvalidInput` error.
+pub(crate) fn shot_frames<'a>(
+    results: &'a [FrameInfo],
+    shot: &Range<usize>,
+) -> io::Result<&'a [FrameInfo]> {
+    let frames = results.get(shot.clone()).filter(|x| !x.is_empty());
+    frames.ok_or_else(|| {
+        let message = format!(
+            "shot {}..{} is empty or outside the {} measured frames",
+            shot.start,
+            shot.end,
+            results.len()
+        );
+        io::Error::new(io::ErrorKind::InvalidInput, message)
+    })
+}
+
+/// Statistics over the frames of one shot.
+#[derive(Debug)]
+pub struct Shot {
+    /// Indices of the shot's frames in the measurements.
+    pub frames: Range<usize>,
+    /// Numbers of the frames the shot spans in the stream. When frames were sampled, this includes
+    /// those skipped up to the next shot's first frame.
+    pub stream_frames: Range<usize>,
+    /// Presentation time of the shot's first frame, in seconds.
+    pub time: Option<f64>,
+    /// Length of the shot in seconds, from the stream's frame rate.
+    pub duration: Option<f64>,
+    pub summary: Summary,
+}
+
+impl Shot {
+    /// Summarises the frames of `results` in the range `frames`, if it is a non-empty range
+    /// within them.
+    pub fn new(
+        results: &[FrameInfo],
+        frames: Range<usize>,
+        frame_rate: Option<f64>,
+    ) -> Option<Self> {
+        let summary = Summary::new(results.get(frames.clone())?)?;
+        let start = results[frames.start].frame;
+        let end = match results.get(frames.end) {
+            Some(next) => next.frame,
+            None => results[frames.end - 1].frame + 1,
+        };
+
+        Some(Shot {
+            stream_frames: start..end,
+            time: results[frames.start].time,
+            duration: frame_rate.map(|rate| (end - start) as f64 / rate),
+            summary,
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::histogram::Histogram;
+
+    fn frames(levels: &[f64]) -> Vec<FrameInfo> {
+        levels
+            .iter()
+            .enumerate()
+            .map(|(i, &pq)| FrameInfo::uniform(i, pq))
+            .collect()
+    }
+
+    /// Frames with their profiles, whose pixels are split between the two code values given.
+    fn profiled_frames(levels: &[(u16, u16)]) -> Vec<FrameInfo> {
+        levels
+            .iter()
+            .enumerate()
+            .map(|(i, &(dark, bright))| {
+                let mut histogram = Histogram::default();
+                for _ in 0..50 {
+                    histogram.add(dark);
+                    histogram.add(bright);
+                }
+
+                FrameInfo {
+                    profile: Some(histogram.profile()),
+                    ..FrameInfo::uniform(i, bright as f64 / 1023.0)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn average_jump() {
+        let results = frames(&[0.1, 0.1, 0.3, 0.3, 0.32, 0.1]);
+        let detector = SceneDetector::new("avg", None).unwrap();
+
+        assert_eq!(
+            detect_shots(&results, detector).unwrap(),
+            [0..2, 2..5, 5..6]
+        );
+        assert_eq!(
+            detect_shots(&results, SceneDetector::AverageJump(0.01)).unwrap(),
+            [0..2, 2..4, 4..5, 5..6]
+        );
+    }
+
+    #[test]
+    fn no_cuts() {
+        let detector = SceneDetector::AverageJump(0.05);
+
+        let shots = detect_shots(&frames(&[0.5; 4]), detector).unwrap();
+        assert_eq!(shots.len(), 1);
+        assert_eq!(shots[0], 0..4);
+
+        let shots = detect_shots(&frames(&[0.5]), detector).unwrap();
+        assert_eq!(shots.len(), 1);
+        assert_eq!(shots[0], 0..1);
+
+        assert!(detect_shots(&[], detector).unwrap().is_empty());
+    }
+
+    #[test]
+    fn histogram() {
+        // The third frame changes half its pixels, the fourth all of them
+        let results = profiled_frames(&[(100, 700), (100, 700), (100, 300), (800, 900)]);
+        let detector = SceneDetector::new("histogram", None).unwrap();
+
+        assert!(detector.needs_profiles());
+        assert_eq!(detect_shots(&results, detector).unwrap(), [0..3, 3..4]);
+        assert_eq!(
+            detect_shots(&results, SceneDetector::Histogram(0.4)).unwrap(),
+            [0..2, 2..3, 3..4]
+        );
+    }
+
+    #[test]
+    fn histogram_needs_profiles() {
+        let results = frames(&[0.1, 0.1]);
+
+        assert_eq!(
+            detect_shots(&results, SceneDetector::Histogram(0.5)),
+            Err(NotMeasured("shot detection by histogram"))
+        );
+        // A single frame has nothing to compare
+        assert!(detect_shots(&results[..1], SceneDetector::Histogram(0.5)).is_ok());
+    }
+
+    #[test]
+    fn detector_names() {
+        assert_eq!(
+            SceneDetector::new("avg", Some(0.1)),
+            Some(SceneDetector::AverageJump(0.1))
+        );
+        assert_eq!(
+            SceneDetector::new("histogram", None),
+            Some(SceneDetector::Histogram(HISTOGRAM_THRESHOLD))
+        );
+        assert_eq!(SceneDetector::new("fade", None), None);
+    }
+
+    #[test]
+    fn sampled_shot() {
+        // Every fourth frame
+        let results = [0.1, 0.1, 0.5, 0.5]
+            .into_iter()
+            .enumerate()
+            .map(|(i, pq)| FrameInfo::uniform(i * 4, pq))
+            .collect::<Vec<_>>();
+
+        let shot = Shot::new(&results, 0..2, Some(24.0)).unwrap();
+        assert_eq!(shot.stream_frames, 0..8);
+        assert_eq!(shot.duration, Some(8.0 / 24.0));
+
+        // The frames after the last measured one are unknown
+        let shot = Shot::new(&results, 2..4, Some(24.0)).unwrap();
+        assert_eq!(shot.stream_frames, 8..13);
+    }
+
+    #[test]
+    fn empty_shot() {
+        let results = frames(&[0.1, 0.5]);
+
+        assert!(Shot::new(&results, 1..1, None).is_none());
+        assert!(Shot::new(&results, 1..3, None).is_none());
+        assert!(Shot::new(&[], 0..0, None).is_none());
+    }
+
+    #[test]
+    fn shot_statistics() {
+        let results = frames(&[0.1, 0.5, 0.6, 0.2]);
+        let shot = Shot::new(&results, 1..3, Some(24.0)).unwrap();
+
+        assert_eq!(shot.duration, Some(2.0 / 24.0));
+        assert_eq!(shot.summary.frames, 2);
+        assert_eq!(shot.summary.max_cll_frame, 2);
+        assert_eq!(shot.summary.max_fall_frame, 2);
+    }
+}
@@ -0,0 +1,145 @@
+use ffmpeg::color::TransferCharacteristic;
+use ffmpeg_next as ffmpeg;
+use std::fmt;
+
+use crate::{
+    bt1886::{SDR_REFERENCE_WHITE, bt1886_to_nits},
+    frame::Matrix,
+    hlg::{HLG_NOMINAL_PEAK, hlg_system_gamma, hlg_to_nits},
+    pq::nits_to_10bit_pq,
+};
+
+/// How a signal encodes light, which decides how its code values are converted to nits.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Transfer {
+    /// SMPTE ST 2084 PQ, which encodes display light directly.
+    #[default]
+    Pq,
+    /// ARIB STD-B67 HLG, which encodes scene light, shown on a display with the given nominal
+    /// peak in nits.
+    Hlg { nominal_peak: f64 },
+    /// The BT.709 OETF that SDR video is encoded with, shown by the BT.1886 EOTF on a display
+    /// with the given reference white in nits.
+    Sdr { reference_white: f64 },
+}
+
+impl Transfer {
+    /// Picks a transfer by name, with HLG shown at `hlg_peak` nits or the reference display's
+    /// 1000 nits, and SDR reference white at `sdr_white` nits or 100 nits.
+    pub fn new(name: &str, hlg_peak: Option<f64>, sdr_white: Option<f64>) -> Option<Self> {
+        match name {
+            "pq" | "smpte2084" => Some(Transfer::Pq),
+            "hlg" | "arib-std-b67" => Some(Transfer::Hlg {
+                nominal_peak: hlg_peak.unwrap_or(HLG_NOMINAL_PEAK),
+            }),
+            "sdr" | "bt709" | "bt1886" => Some(Transfer::Sdr {
+                reference_white: sdr_white.unwrap_or(SDR_REFERENCE_WHITE),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The transfer FFmpeg reports, if it is one that can be measured.
+    pub fn from_ffmpeg(
+        trc: TransferCharacteristic,
+        hlg_peak: Option<f64>,
+        sdr_white: Option<f64>,
+    ) -> Option<Self> {
+        let name = match trc {
+            TransferCharacteristic::SMPTE2084 => "pq",
+            TransferCharacteristic::ARIB_STD_B67 => "hlg",
+            // These all share the BT.709 OETF
+            TransferCharacteristic::BT709
+            | TransferCharacteristic::SMPTE170M
+            | TransferCharacteristic::BT2020_10
+            | TransferCharacteristic::BT2020_12 => "sdr",
+            _ => return None,
+        };
+
+        Transfer::new(name, hlg_peak, sdr_white)
+    }
+
+    /// The matrix Y'CbCr signals with this transfer most likely use, if the stream doesn't say.
+    pub fn default_matrix(self) -> Matrix {
+        match self {
+            Transfer::Pq | Transfer::Hlg { .. } => Matrix::Bt2020,
+            Transfer::Sdr { .. } => Matrix::Bt709,
+        }
+    }
+
+    /// A conversion from R', G' and B' signal values to the 10-bit PQ code values of the light
+    /// the display shows for each.
+    pub(crate) fn to_10bit_pq(self) -> impl Fn([f64; 3]) -> [u16; 3] {
+        // Worked out once, rather than for every pixel
+        let system_gamma = match self {
+            Transfer::Hlg { nominal_peak } => hlg_system_gamma(nominal_peak),
+            Transfer::Pq | Transfer::Sdr { .. } => 1.0,
+        };
+
+        move |rgb| match self {
+            Transfer::Pq => rgb.map(|x| (x * 1023.0).round() as u16),
+            Transfer::Hlg { nominal_peak } => {
+                hlg_to_nits(rgb, nominal_peak, system_gamma).map(nits_to_10bit_pq)
+            }
+            Transfer::Sdr { reference_white } => {
+                rgb.map(|x| nits_to_10bit_pq(bt1886_to_nits(x, reference_white)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transfer::Pq => write!(f, "PQ"),
+            Transfer::Hlg { nominal_peak } => write!(f, "HLG (nominal peak {nominal_peak} nits)"),
+            Transfer::Sdr { reference_white } => {
+                write!(f, "SDR BT.1886 (reference white {reference_white} nits)")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(Transfer::new("smpte2084", None, None), Some(Transfer::Pq));
+        assert_eq!(
+            Transfer::new("hlg", None, None),
+            Some(Transfer::Hlg {
+                nominal_peak: 1000.0
+            })
+        );
+        assert_eq!(
+            Transfer::new("arib-std-b67", Some(2000.0), None),
+            Some(Transfer::Hlg {
+                nominal_peak: 2000.0
+            })
+        );
+        assert_eq!(
+            Transfer::new("bt1886", None, Some(203.0)),
+            Some(Transfer::Sdr {
+                reference_white: 203.0
+            })
+        );
+        assert_eq!(Transfer::new("linear", None, None), None);
+    }
+
+    #[test]
+    fn pq_code_values() {
+        let to_pq = Transfer::Pq.to_10bit_pq();
+        assert_eq!(to_pq([0.0, 0.5, 1.0]), [0, 512, 1023]);
+
+        // HLG reference white is 203 nits on the 1000 nit reference display
+        let to_pq = Transfer::new("hlg", None, None).unwrap().to_10bit_pq();
+        assert_eq!(to_pq([0.75; 3]), [nits_to_10bit_pq(203.0); 3]);
+        assert_eq!(to_pq([0.0; 3]), [0; 3]);
+
+        let to_pq = Transfer::new("sdr", None, None).unwrap().to_10bit_pq();
+        assert_eq!(to_pq([1.0; 3]), [nits_to_10bit_pq(100.0); 3]);
+        assert_eq!(to_pq([0.0; 3]), [0; 3]);
+    }
+}